@@ -0,0 +1,54 @@
+use crate::emu::{EXECUTED, MODIFIED_CODE, READ, WRITTEN};
+
+// The text form of `Chip8::access`: each run of addresses used the same way,
+// as `from-to flags`, where the flags are x (executed), r (read as data),
+// w (written) and m (code modified), or - for each that isn't so:
+//
+//   000-04f -r--
+//   200-2a3 x---
+//   2a4-2b3 -r--
+//   300-302 --w-
+//
+// Addresses that were never touched are left out.
+
+static FLAGS: [(u8, char); 4] = [(EXECUTED, 'x'), (READ, 'r'), (WRITTEN, 'w'), (MODIFIED_CODE, 'm')];
+
+pub fn flags(bits: u8) -> String {
+    FLAGS.iter().map(|&(bit, c)| if bits & bit != 0 { c } else { '-' }).collect()
+}
+
+pub fn export(access: &[u8]) -> String {
+    let mut text = String::new();
+    let mut start = 0;
+    while start < access.len() {
+        let end = start + access[start..].iter().take_while(|&&bits| bits == access[start]).count();
+        if access[start] != 0 {
+            text.push_str(&format!("{:03x}-{:03x} {}\n", start, end - 1, flags(access[start])));
+        }
+        start = end;
+    }
+    text
+}
+
+pub fn parse(text: &str) -> Result<Vec<u8>, String> {
+    let mut access = vec![0; 0x1000];
+    for (n, line) in text.lines().enumerate() {
+        let bad = || format!("line {}: expected from-to flags, not {}", n + 1, line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (range, line_flags) = line.split_once(' ').ok_or_else(bad)?;
+        let (from, to) = range.split_once('-').ok_or_else(bad)?;
+        let from = usize::from_str_radix(from, 16).map_err(|_| bad())?;
+        let to = usize::from_str_radix(to, 16).map_err(|_| bad())?;
+        if from > to || to >= access.len() {
+            return Err(bad());
+        }
+        let mut bits = 0;
+        for c in line_flags.trim().chars().filter(|&c| c != '-') {
+            bits |= FLAGS.iter().find(|&&(_, f)| f == c).ok_or_else(bad)?.0;
+        }
+        access[from..=to].fill(bits);
+    }
+    Ok(access)
+}
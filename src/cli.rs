@@ -0,0 +1,397 @@
+use crate::emu::{Chip8Options, PRESET_NAMES, QUIRK_NAMES};
+use crate::filter::{Filter, FILTER_NAMES};
+use crate::keymap::LAYOUT_NAMES;
+use crate::palette::{Palette, THEME_NAMES};
+use crate::romdb::RomProfile;
+use crate::settings::RomSettings;
+use crate::timing::Timing;
+use std::ops::RangeInclusive;
+
+pub static USAGE: &str = "\
+Usage:
+  chip8 [run] <rom> [options]       play a ROM in a window
+  chip8 headless <rom> [options]    run a ROM without a window and print the screen
+  chip8 debug <rom> [options]       debug a ROM from the terminal, without a window
+  chip8 info <rom>                  show information about a ROM
+  chip8 disasm <rom> [--access-map <file>]
+                                    disassemble a ROM, using an access map (see below)
+                                    to tell code from data
+  chip8 tracediff <a> <b> [--context <n>]
+                                    show where two traces (from --trace) first differ
+  chip8 --help                      show this message
+
+Options:
+  --speed <multiplier>    emulation speed relative to the default (1 = 480 instructions/s)
+  --ips <n>               emulation speed in instructions per second
+  --timing <ips|vip>      ips: a fixed instruction rate (default); vip: charge each
+                          instruction what it cost on the COSMAC VIP
+  --quirk <name[=off]>    enable (or disable) a compatibility quirk; may be repeated
+  --preset <name>         start from a set of quirks for a particular interpreter
+  --keymap <layout|file>  keyboard layout or keymap file
+  --scale <n>             initial window size as a multiple of 64x32 (default 12)
+  --scaling <fit|integer> fit: fill the window; integer: only whole multiples of the
+                          screen size, for evenly sized pixels (default fit)
+  --palette <theme|colours>
+                          a colour theme, or pixel colours as hex RGB: fg,bg (e.g.
+                          33ff66,000000) or, for XO-CHIP's two planes, fg,bg,fg2,both
+  --filter <name>         upscaling filter: none, scale2x, scale3x or scanlines
+                          (default none)
+  --persistence <n>       fade pixels out over n frames instead of turning them off
+                          at once, to reduce flicker (default 0: off)
+  --seed <n>              seed for the random number generator (CXNN)
+  --frames <n>            headless only: number of 60 Hz frames to run (default 600)
+  --debug                 also take debugger commands from the terminal while playing
+  --trace <file>          write a line per instruction run (registers after it) to file
+  --trace-pc <from-to>    only trace instructions at these addresses (hex, e.g. 200-2ff)
+  --trace-cycles <from-to>
+                          only trace these cycles (either end may be left out: 1000-)
+  --profile <file>        count the instructions run at each address and write the
+                          hot spots, and how much of the ROM ran, to file on exit
+  --heatmap <file>        write the counts as a picture of the address space (BMP)
+  --access-map <file>     record which addresses run, are read as data or are written,
+                          and write the map to file on exit
+  --gdb <port>            run and debug only: serve GDB's remote protocol on a
+                          localhost port (target remote localhost:<port>)
+
+Hotkeys:
+  Esc                     quit
+  F2                      show / hide the debug overlay
+  F3 / F4                 slower / faster
+  F5                      pause / resume
+  F6                      step one instruction (Shift: 100 instructions)
+  F7                      advance one frame
+  F8                      next colour theme
+  F9                      next keyboard layout
+  F10                     open / close the memory viewer
+  F11                     toggle fullscreen
+  F12                     open / close the sprite viewer
+
+Speed, colour theme and keyboard layout changes are remembered per ROM, in
+settings.cfg in the config directory.
+";
+
+pub const DEFAULT_IPS: f64 = 480.0;
+pub const MIN_IPS: f64 = 60.0;
+pub const MAX_IPS: f64 = 60_000.0;
+
+// flags that are on or off, so don't need a value
+const SWITCHES: [&str; 1] = ["debug"];
+
+pub enum Command {
+    Run(RunOptions),
+    Headless(RunOptions, u64),
+    Debug(RunOptions),
+    Info(String),
+    // the ROM, and an access map to tell its code from its data
+    Disasm(String, Option<String>),
+    // two trace files, and the lines of context to show
+    TraceDiff(String, String, usize),
+    Help,
+}
+
+pub struct RunOptions {
+    pub rom: String,
+    // None: use the ROM's profile, if there is one
+    pub ips: Option<f64>,
+    pub timing: Timing,
+    pub preset: Option<Chip8Options>,
+    // applied on top of the preset, in order
+    pub quirks: Vec<(String, bool)>,
+    pub keymap: Option<String>,
+    pub scale: u32,
+    pub integer_scaling: bool,
+    // a theme name or colours, see `Palette::parse`
+    pub palette: Option<String>,
+    pub filter: Filter,
+    // frames for pixels to fade out over; 0 for none
+    pub persistence: u32,
+    pub seed: Option<u64>,
+    pub debug: bool,
+    // port for the GDB server
+    pub gdb: Option<u16>,
+    pub trace: Option<String>,
+    pub trace_pc: Option<RangeInclusive<usize>>,
+    pub trace_cycles: Option<RangeInclusive<u64>>,
+    pub profile: Option<String>,
+    pub heatmap: Option<String>,
+    pub access_map: Option<String>,
+}
+
+impl RunOptions {
+    fn new(rom: String) -> RunOptions {
+        RunOptions {
+            rom,
+            ips: None,
+            timing: Timing::Ips,
+            preset: None,
+            quirks: Vec::new(),
+            keymap: None,
+            scale: 12,
+            integer_scaling: false,
+            palette: None,
+            filter: Filter::None,
+            persistence: 0,
+            seed: None,
+            debug: false,
+            gdb: None,
+            trace: None,
+            trace_pc: None,
+            trace_cycles: None,
+            profile: None,
+            heatmap: None,
+            access_map: None,
+        }
+    }
+
+    // command line first, then the ROM's profile, then the defaults
+    pub fn chip8_options(&self, profile: Option<&RomProfile>) -> Chip8Options {
+        let mut options = match (self.preset, profile) {
+            (Some(preset), _) => preset,
+            (None, Some(profile)) => profile.options(),
+            (None, None) => Chip8Options::default(),
+        };
+        for (name, enabled) in &self.quirks {
+            // names were checked while parsing
+            options.set_quirk(name, *enabled).unwrap();
+        }
+        options
+    }
+
+    // fills in what the command line left out: from the settings saved for
+    // this ROM, then the ROM's profile, then the settings saved for all ROMs
+    pub fn fill_in(&mut self, saved: &RomSettings, profile: Option<&RomProfile>, defaults: &RomSettings) {
+        self.ips = self.ips.or(saved.ips).or_else(|| profile?.tickrate.map(|t| t as f64 * 60.0)).or(defaults.ips);
+        self.keymap = self.keymap.take().or_else(|| saved.keymap.clone()).or_else(|| defaults.keymap.clone());
+        self.palette = self.palette.take().or_else(|| saved.palette.clone()).or_else(|| defaults.palette.clone());
+    }
+
+    pub fn ips(&self, profile: Option<&RomProfile>) -> f64 {
+        self.ips
+            .or_else(|| profile?.tickrate.map(|t| t as f64 * 60.0))
+            .unwrap_or(DEFAULT_IPS)
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        return Ok(Command::Help);
+    }
+    let (subcommand, rest) = match args.first().map(|a| &a[..]) {
+        None => return Err("no ROM given".to_string()),
+        Some(s @ ("run" | "headless" | "debug" | "info" | "disasm" | "tracediff")) => (s, &args[1..]),
+        Some(_) => ("run", args),
+    };
+
+    let mut files = Vec::new();
+    let mut flags = Vec::new();
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(flag) = arg.strip_prefix("--") {
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name, value.to_string()),
+                // switches don't take a value
+                None if SWITCHES.contains(&flag) => (flag, "on".to_string()),
+                None => {
+                    let value = iter
+                        .next()
+                        .ok_or_else(|| format!("--{} needs a value", flag))?;
+                    (flag, value.clone())
+                }
+            };
+            flags.push((name.to_string(), value));
+        } else {
+            files.push(arg.clone());
+        }
+    }
+
+    if subcommand == "tracediff" {
+        let [a, b]: [String; 2] = files.try_into().map_err(|_| "tracediff: expected two trace files".to_string())?;
+        let mut context = 5;
+        for (name, value) in flags {
+            match &name[..] {
+                "context" => context = parse_number(&name, &value)?,
+                _ => return Err(format!("tracediff does not take --{}", name)),
+            }
+        }
+        return Ok(Command::TraceDiff(a, b, context));
+    }
+
+    if let Some(arg) = files.get(1) {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    let rom = files.pop().ok_or_else(|| format!("{}: no ROM given", subcommand))?;
+
+    if subcommand == "info" || subcommand == "disasm" {
+        let mut access_map = None;
+        for (name, value) in flags {
+            match &name[..] {
+                "access-map" if subcommand == "disasm" => access_map = Some(value),
+                _ => return Err(format!("{} does not take --{}", subcommand, name)),
+            }
+        }
+        return Ok(if subcommand == "info" {
+            Command::Info(rom)
+        } else {
+            Command::Disasm(rom, access_map)
+        });
+    }
+
+    let mut options = RunOptions::new(rom);
+    let mut frames = 600;
+    let mut speed_given = false;
+    for (name, value) in flags {
+        match &name[..] {
+            "speed" | "ips" => {
+                if speed_given {
+                    return Err("--speed and --ips can only be given once".to_string());
+                }
+                speed_given = true;
+                let n: f64 = parse_number(&name, &value)?;
+                let ips = if name == "speed" { n * DEFAULT_IPS } else { n };
+                options.ips = Some(ips);
+                if !(MIN_IPS..=MAX_IPS).contains(&ips) {
+                    return Err(format!(
+                        "--{} out of range (between {} and {} instructions per second, or a speed of {} to {})",
+                        name,
+                        MIN_IPS,
+                        MAX_IPS,
+                        MIN_IPS / DEFAULT_IPS,
+                        MAX_IPS / DEFAULT_IPS
+                    ));
+                }
+            }
+            "timing" => {
+                options.timing = match &value[..] {
+                    "ips" => Timing::Ips,
+                    "vip" => Timing::Vip,
+                    _ => return Err(format!("--timing: expected ips or vip, not {}", value)),
+                };
+            }
+            "quirk" => {
+                let (quirk, enabled) = match value.split_once('=') {
+                    Some((quirk, "on")) => (quirk, true),
+                    Some((quirk, "off")) => (quirk, false),
+                    Some(_) => return Err(format!("--quirk {}: expected name=on or name=off", value)),
+                    None => (&value[..], true),
+                };
+                if !QUIRK_NAMES.contains(&quirk) {
+                    return Err(format!(
+                        "unknown quirk: {} (known quirks: {})",
+                        quirk,
+                        QUIRK_NAMES.join(", ")
+                    ));
+                }
+                options.quirks.push((quirk.to_string(), enabled));
+            }
+            "preset" => {
+                options.preset = Some(Chip8Options::preset(&value).ok_or_else(|| {
+                    format!("unknown preset: {} (known presets: {})", value, PRESET_NAMES.join(", "))
+                })?);
+            }
+            "keymap" => options.keymap = Some(value),
+            "scale" => {
+                options.scale = parse_number(&name, &value)?;
+                if !(1..=64).contains(&options.scale) {
+                    return Err("--scale must be between 1 and 64".to_string());
+                }
+            }
+            "scaling" => {
+                options.integer_scaling = match &value[..] {
+                    "fit" => false,
+                    "integer" => true,
+                    _ => return Err(format!("--scaling: expected fit or integer, not {}", value)),
+                };
+            }
+            "palette" => {
+                Palette::parse(&value).map_err(|e| format!("--palette: {}", e))?;
+                options.palette = Some(value);
+            }
+            "filter" => {
+                options.filter = Filter::from_name(&value).ok_or_else(|| {
+                    format!("unknown filter: {} (known filters: {})", value, FILTER_NAMES.join(", "))
+                })?;
+            }
+            "persistence" => {
+                options.persistence = parse_number(&name, &value)?;
+                if options.persistence > 60 {
+                    return Err("--persistence must be between 0 and 60 frames".to_string());
+                }
+            }
+            "seed" => options.seed = Some(parse_number(&name, &value)?),
+            "debug" if subcommand == "run" => {
+                options.debug = match &value[..] {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("--debug: expected on or off, not {}", value)),
+                };
+            }
+            "gdb" if subcommand != "headless" => options.gdb = Some(parse_number(&name, &value)?),
+            "trace" => options.trace = Some(value),
+            "trace-pc" => {
+                let range = parse_range(&name, &value, 16)?;
+                // an open end is the end of memory, but a given one has to be in it
+                let open_end = value.ends_with('-');
+                if *range.start() > 0xFFF || (!open_end && *range.end() > 0xFFF) {
+                    return Err(format!("--{}: addresses go up to fff, not {}", name, value));
+                }
+                options.trace_pc = Some(*range.start() as usize..=(*range.end()).min(0xFFF) as usize);
+            }
+            "trace-cycles" => options.trace_cycles = Some(parse_range(&name, &value, 10)?),
+            "profile" => options.profile = Some(value),
+            "heatmap" => options.heatmap = Some(value),
+            "access-map" => options.access_map = Some(value),
+            "frames" if subcommand == "headless" => frames = parse_number(&name, &value)?,
+            _ => return Err(format!("unknown option: --{}", name)),
+        }
+    }
+
+    if (options.trace_pc.is_some() || options.trace_cycles.is_some()) && options.trace.is_none() {
+        return Err("--trace-pc and --trace-cycles need --trace".to_string());
+    }
+
+    if options.debug && options.gdb.is_some() {
+        return Err("--debug and --gdb can't be used together".to_string());
+    }
+
+    if speed_given && options.timing == Timing::Vip {
+        return Err("--speed and --ips don't apply with --timing vip".to_string());
+    }
+
+    Ok(match subcommand {
+        "headless" => Command::Headless(options, frames),
+        "debug" => Command::Debug(options),
+        _ => Command::Run(options),
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("--{}: not a valid number: {}", name, value))
+}
+
+// `from-to`, inclusive, where either end can be left out
+fn parse_range(name: &str, value: &str, radix: u32) -> Result<RangeInclusive<u64>, String> {
+    let bad = || format!("--{}: expected from-to, not {}", name, value);
+    let (from, to) = value.split_once('-').ok_or_else(bad)?;
+    let bound = |s: &str, default| match s {
+        "" => Ok(default),
+        s => u64::from_str_radix(s.trim_start_matches("0x"), radix).map_err(|_| bad()),
+    };
+    let range = bound(from, 0)?..=bound(to, u64::MAX)?;
+    if range.is_empty() {
+        return Err(bad());
+    }
+    Ok(range)
+}
+
+pub fn names_help() -> String {
+    format!(
+        "Quirks: {}\nPresets: {}\nKeyboard layouts: {}\nColour themes: {}\nFilters: {}",
+        QUIRK_NAMES.join(", "),
+        PRESET_NAMES.join(", "),
+        LAYOUT_NAMES.join(", "),
+        THEME_NAMES.join(", "),
+        FILTER_NAMES.join(", ")
+    )
+}
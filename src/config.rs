@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+// Minimal config file format shared by the frontend:
+//
+//   # comment
+//   key = value
+//   [section]
+//   key = value
+//
+// Entries before the first section header belong to the "" section.
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+pub struct ConfigEntry {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut entries = Vec::new();
+        let mut section = String::new();
+        for (i, raw_line) in text.lines().enumerate() {
+            let line = match raw_line.find('#') {
+                Some(pos) => &raw_line[..pos],
+                None => raw_line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix('[') {
+                match rest.strip_suffix(']') {
+                    Some(name) => section = name.trim().to_string(),
+                    None => return Err(format!("line {}: unterminated section header", i + 1)),
+                }
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => entries.push(ConfigEntry {
+                    section: section.clone(),
+                    key: key.trim().to_string(),
+                    value: value.trim().to_string(),
+                    line: i + 1,
+                }),
+                None => return Err(format!("line {}: expected `key = value`", i + 1)),
+            }
+        }
+        Ok(Config { entries })
+    }
+
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+
+    pub fn section<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ConfigEntry> + 'a {
+        self.entries.iter().filter(move |e| e.section == name)
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        // later entries win, like repeated assignments would
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.section == section && e.key == key)
+            .map(|e| &e.value[..])
+    }
+}
+
+// $XDG_CONFIG_HOME/chip8, falling back to ~/.config/chip8 (or %APPDATA%\chip8 on windows)
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA")?),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("chip8"))
+}
@@ -0,0 +1,563 @@
+use crate::disasm::{disassemble, disassemble_range};
+use crate::emu::{self, Chip8};
+use crate::expr::Expr;
+use crate::history::History;
+
+pub static HELP: &str = "\
+Commands:
+  break <addr>          b   stop when PC reaches addr
+  watch <target>        w   stop when a register or memory byte changes
+  catch <event>             stop when something happens: unknown (opcode),
+                            collision (DXYN setting VF), key (FX0A waiting),
+                            sound (the sound timer starting) or lowwrite
+                            (a write below 0x200)
+  ... if <condition>        only stop when the condition is true, e.g.
+                            break 0x2a4 if v3 == 0x10 && [i] != 0
+  condition <n> [cond]      change or remove the condition of n
+  ignore <n> <count>        let n be hit count more times without stopping
+  delete [n]            d   remove breakpoint/watchpoint n (all if no n)
+  info                      list breakpoints and watchpoints, with hit counts
+  step [n]              s   run n instructions (default 1)
+  next                  n   step, running subroutine calls through to their return
+  finish                f   run until the current subroutine returns
+  continue [frames]     c   run until something stops it (at most this many frames)
+  reverse-step [n]      rs  go back n instructions (default 1)
+  reverse-continue      rc  go back to the last place something would have stopped
+  regs                  r   registers, timers and stack pointer
+  stack                     the call stack
+  mem <addr> [len]      x   memory in hex (default 64 bytes)
+  disasm [addr] [n]     l   disassemble n instructions around addr (default PC)
+  set <target> <value>      change a register or memory byte
+  quit                  q   exit
+Targets: v0-vf, i, pc, sp, dt, st, [addr] (a memory byte)
+Conditions: targets, numbers, [expr] for memory, == != < <= > >= && || ! + - & | ^ ()
+Numbers are decimal, or hex with 0x. An empty line repeats the last command.
+";
+
+// something that can be watched or set
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    Mem(usize),
+}
+
+impl Target {
+    pub fn parse(s: &str) -> Result<Target, String> {
+        let lower = s.to_ascii_lowercase();
+        if let Some(addr) = lower.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            return Ok(Target::Mem(parse_number(addr)? & 0xFFF));
+        }
+        Ok(match &lower[..] {
+            "i" => Target::I,
+            "pc" => Target::Pc,
+            "sp" => Target::Sp,
+            "dt" => Target::Dt,
+            "st" => Target::St,
+            _ => match lower.strip_prefix('v').map(|n| usize::from_str_radix(n, 16)) {
+                Some(Ok(n)) if n < 16 => Target::V(n),
+                _ => return Err(format!("not a register or [address]: {}", s)),
+            },
+        })
+    }
+
+    pub fn get(&self, chip8: &Chip8) -> usize {
+        match *self {
+            Target::V(n) => chip8.regs_v[n] as usize,
+            Target::I => chip8.reg_i,
+            Target::Pc => chip8.reg_pc,
+            Target::Sp => chip8.stack_pointer,
+            Target::Dt => chip8.delay_timer as usize,
+            Target::St => chip8.sound_timer as usize,
+            Target::Mem(addr) => chip8.memory[addr] as usize,
+        }
+    }
+
+    pub fn set(&self, chip8: &mut Chip8, value: usize) -> Result<(), String> {
+        let max = match self {
+            Target::I => 0xFFF,
+            // the instruction's second byte is fetched from PC + 1
+            Target::Pc => 0xFFE,
+            Target::Sp => 16,
+            _ => 0xFF,
+        };
+        if value > max {
+            return Err(format!("{} can't be more than {:#x}", self, max));
+        }
+        match *self {
+            Target::V(n) => chip8.regs_v[n] = value as u8,
+            Target::I => chip8.reg_i = value,
+            Target::Pc => chip8.reg_pc = value,
+            Target::Sp => chip8.stack_pointer = value,
+            Target::Dt => chip8.delay_timer = value as u8,
+            Target::St => chip8.sound_timer = value as u8,
+            Target::Mem(addr) => chip8.memory[addr] = value as u8,
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Target::V(n) => write!(f, "V{:X}", n),
+            Target::I => write!(f, "I"),
+            Target::Pc => write!(f, "PC"),
+            Target::Sp => write!(f, "SP"),
+            Target::Dt => write!(f, "DT"),
+            Target::St => write!(f, "ST"),
+            Target::Mem(addr) => write!(f, "[{:#05x}]", addr),
+        }
+    }
+}
+
+// decimal, or hex with 0x
+pub fn parse_number(s: &str) -> Result<usize, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("not a number: {}", s))
+}
+
+// what the frontend should do after a command
+#[derive(PartialEq, Debug)]
+pub enum Action {
+    None,
+    // the machine moved: show where it is now
+    Stepped,
+    // run until `check` says to stop, or for at most this many frames
+    Continue(Option<u64>),
+    Quit,
+}
+
+pub static EVENT_NAMES: [&str; 5] = ["unknown", "collision", "key", "sound", "lowwrite"];
+
+// things that happen while running, to stop on (`catch`)
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Event {
+    // an opcode the interpreter doesn't implement
+    Unknown,
+    // DXYN erasing a pixel (VF = 1)
+    Collision,
+    // FX0A starting to wait for a key
+    Key,
+    // the sound timer being started
+    Sound,
+    // a write into the interpreter's memory below 0x200
+    LowWrite,
+}
+
+#[derive(Clone)]
+enum Kind {
+    Break(usize),
+    // with the value last seen
+    Watch(Target, usize),
+    Catch(Event),
+}
+
+#[derive(Clone)]
+struct Point {
+    id: u32,
+    kind: Kind,
+    // the condition's text, and the condition
+    condition: Option<(String, Expr)>,
+    hits: u32,
+    // hits to let go by before stopping
+    ignore: u32,
+}
+
+impl Point {
+    fn describe(&self) -> String {
+        let mut text = match &self.kind {
+            Kind::Break(addr) => format!("{}: break at {:03x}", self.id, addr),
+            Kind::Watch(target, _) => format!("{}: watch {}", self.id, target),
+            Kind::Catch(event) => format!("{}: catch {}", self.id, EVENT_NAMES[*event as usize]),
+        };
+        if let Some((condition, _)) = &self.condition {
+            text.push_str(&format!(" if {}", condition));
+        }
+        text
+    }
+}
+
+// run until the stack is back below a depth, optionally at a particular address
+#[derive(Clone, Copy)]
+struct Until {
+    depth: usize,
+    pc: Option<usize>,
+}
+
+pub struct Debugger {
+    points: Vec<Point>,
+    next_id: u32,
+    until: Option<Until>,
+    // the instruction at PC at the last check, which is the one that ran
+    // since, unless the machine was waiting (so a breakpoint fires on arriving
+    // at it, every time, but not on every check while the machine waits there)
+    last_opcode: Option<u16>,
+    last_sound_timer: u8,
+    // I at the last check, where the instruction that ran since wrote to
+    last_i: usize,
+    last_command: String,
+    history: History,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            points: Vec::new(),
+            next_id: 1,
+            until: None,
+            last_opcode: None,
+            last_sound_timer: 0,
+            last_i: 0,
+            last_command: String::new(),
+            history: History::new(),
+        }
+    }
+
+    // Called after every instruction while running. Reports and returns true
+    // when a breakpoint, watchpoint or catchpoint is hit, or a next/finish is done.
+    pub fn check(&mut self, chip8: &Chip8) -> bool {
+        self.history.record(chip8);
+        let mut stop = false;
+        for (n, detail) in self.hits(chip8) {
+            let point = &self.points[n];
+            println!("Hit {}{} (hit {} time{})", point.describe(), detail, point.hits, if point.hits == 1 { "" } else { "s" });
+            stop = true;
+        }
+        if let Some(until) = self.until {
+            if chip8.stack_pointer < until.depth
+                || (chip8.stack_pointer == until.depth && until.pc == Some(chip8.reg_pc))
+            {
+                stop = true;
+            }
+        }
+        self.sync(chip8);
+        if stop {
+            self.until = None;
+        }
+        stop
+    }
+
+    // for cycles run outside `check`, so they can still be gone back over
+    pub fn record(&mut self, chip8: &Chip8) {
+        self.history.record(chip8);
+    }
+
+    // the points that stop on the instruction that just ran, with what happened
+    fn hits(&mut self, chip8: &Chip8) -> Vec<(usize, String)> {
+        let mut hits = Vec::new();
+        for (n, point) in self.points.iter_mut().enumerate() {
+            // what happened, if the point was hit
+            let hit = match &mut point.kind {
+                Kind::Break(addr) => (chip8.reg_pc == *addr && self.last_opcode.is_some()).then(String::new),
+                Kind::Watch(target, old) => {
+                    let new = target.get(chip8);
+                    let change = (new != *old).then(|| format!(": {:#x} -> {:#x}", old, new));
+                    *old = new;
+                    change
+                }
+                Kind::Catch(event) => {
+                    let ran = |is: fn(u16) -> bool| self.last_opcode.is_some_and(is);
+                    match event {
+                        Event::Unknown => ran(|op| !emu::is_implemented(op)).then(String::new),
+                        Event::Collision => (ran(|op| op >> 12 == 0xD) && chip8.regs_v[0xF] == 1).then(String::new),
+                        Event::Key => ran(|op| op & 0xF0FF == 0xF00A).then(String::new),
+                        Event::Sound => (self.last_sound_timer == 0 && chip8.sound_timer > 0).then(String::new),
+                        // only FX33 and FX55 write memory, starting at I
+                        Event::LowWrite => (ran(|op| matches!(op & 0xF0FF, 0xF033 | 0xF055)) && self.last_i < 0x200)
+                            .then(|| format!(": {:03x} written", self.last_i)),
+                    }
+                }
+            };
+            let detail = match hit {
+                Some(detail) if point.condition.as_ref().is_none_or(|(_, c)| c.is_true(chip8)) => detail,
+                _ => continue,
+            };
+            point.hits += 1;
+            if point.hits <= point.ignore {
+                continue;
+            }
+            hits.push((n, detail));
+        }
+        hits
+    }
+
+    // Replays the history up to just before where the machine is, with the
+    // same points, and goes back to the last place one would have stopped.
+    fn reverse_continue(&mut self, chip8: &mut Chip8) -> Result<Action, String> {
+        let mut machine = self.history.oldest().ok_or("no history to go back through")?.clone();
+        let mut replay = Debugger::new();
+        replay.points = self.points.iter().map(|p| Point { hits: 0, ignore: 0, ..p.clone() }).collect();
+        replay.sync(&machine);
+        let mut found = None;
+        self.history.replay(&mut machine, chip8.cycles.saturating_sub(1), |machine| {
+            if let Some((n, detail)) = replay.hits(machine).pop() {
+                found = Some((machine.cycles, n, detail));
+            }
+            replay.sync(machine);
+        });
+        let target = match &found {
+            Some((cycles, n, detail)) => {
+                println!("Back to {}{}", self.points[*n].describe(), detail);
+                *cycles
+            }
+            None => {
+                println!("Nothing would have stopped; back to the start of the history");
+                self.history.oldest().map_or(0, |oldest| oldest.cycles)
+            }
+        };
+        *chip8 = self.history.rewind(target)?;
+        self.sync(chip8);
+        Ok(Action::Stepped)
+    }
+
+    // takes the machine as it is now as the starting point for checks, so
+    // changes made while stopped don't trigger watchpoints
+    fn sync(&mut self, chip8: &Chip8) {
+        for point in self.points.iter_mut() {
+            if let Kind::Watch(target, old) = &mut point.kind {
+                *old = target.get(chip8);
+            }
+        }
+        self.last_opcode = if chip8.awaiting_keypress || chip8.awaiting_vblank {
+            None
+        } else {
+            Some(chip8.opcode())
+        };
+        self.last_sound_timer = chip8.sound_timer;
+        self.last_i = chip8.reg_i;
+    }
+
+    pub fn command(&mut self, chip8: &mut Chip8, line: &str) -> Result<Action, String> {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.trim().to_string();
+            self.last_command.clone()
+        };
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(Action::None),
+        };
+        let args: Vec<&str> = words.collect();
+        self.history.record(chip8);
+        self.sync(chip8);
+        let arg = |n: usize| -> Result<usize, String> {
+            parse_number(args.get(n).ok_or_else(|| format!("{}: missing argument", command))?)
+        };
+        let action = match command {
+            "break" | "b" | "watch" | "w" | "catch" => {
+                let what = args.first().ok_or_else(|| format!("{}: missing argument", command))?;
+                let kind = match command {
+                    "break" | "b" => Kind::Break(parse_number(what)? & 0xFFF),
+                    "watch" | "w" => {
+                        let target = Target::parse(what)?;
+                        Kind::Watch(target, target.get(chip8))
+                    }
+                    _ => match EVENT_NAMES.iter().position(|name| name == what) {
+                        Some(n) => Kind::Catch(EVENTS[n]),
+                        None => return Err(format!("unknown event: {} (events: {})", what, EVENT_NAMES.join(", "))),
+                    },
+                };
+                let condition = match args.get(1) {
+                    None => None,
+                    Some(&"if") => Some(parse_condition(&args[2..])?),
+                    Some(_) => return Err(format!("{}: expected if <condition>", command)),
+                };
+                let point = Point {
+                    id: self.next_id,
+                    kind,
+                    condition,
+                    hits: 0,
+                    ignore: 0,
+                };
+                println!("Added {}", point.describe());
+                self.points.push(point);
+                self.next_id += 1;
+                self.sync(chip8);
+                Action::None
+            }
+            "condition" => {
+                let condition = if args.len() > 1 { Some(parse_condition(&args[1..])?) } else { None };
+                self.point(arg(0)?)?.condition = condition;
+                Action::None
+            }
+            "ignore" => {
+                let count = arg(1)? as u32;
+                let point = self.point(arg(0)?)?;
+                point.ignore = point.hits + count;
+                Action::None
+            }
+            "delete" | "d" => {
+                if args.is_empty() {
+                    self.points.clear();
+                } else {
+                    let id = arg(0)?;
+                    self.point(id)?;
+                    self.points.retain(|p| p.id as usize != id);
+                }
+                Action::None
+            }
+            "info" => {
+                if self.points.is_empty() {
+                    println!("No breakpoints, watchpoints or catchpoints");
+                }
+                for point in &self.points {
+                    let mut line = point.describe();
+                    if point.hits > 0 {
+                        line.push_str(&format!(" (hit {} times)", point.hits));
+                    }
+                    if point.ignore > point.hits {
+                        line.push_str(&format!(" (ignoring the next {})", point.ignore - point.hits));
+                    }
+                    println!("{}", line);
+                }
+                Action::None
+            }
+            "step" | "s" => {
+                let n = if args.is_empty() { 1 } else { arg(0)? };
+                self.step(chip8, n)
+            }
+            "next" | "n" => {
+                if chip8.opcode() >> 12 == 0x2 {
+                    self.until = Some(Until {
+                        depth: chip8.stack_pointer,
+                        pc: Some(chip8.reg_pc + 2),
+                    });
+                    Action::Continue(None)
+                } else {
+                    self.step(chip8, 1)
+                }
+            }
+            "finish" | "f" => {
+                if chip8.stack_pointer == 0 {
+                    return Err("finish: not in a subroutine".to_string());
+                }
+                self.until = Some(Until {
+                    depth: chip8.stack_pointer,
+                    pc: None,
+                });
+                Action::Continue(None)
+            }
+            "continue" | "c" => Action::Continue(if args.is_empty() { None } else { Some(arg(0)? as u64) }),
+            "reverse-step" | "rs" => {
+                let n = if args.is_empty() { 1 } else { arg(0)? as u64 };
+                let target = self.history.instructions_back(chip8, n)?;
+                *chip8 = self.history.rewind(target)?;
+                self.sync(chip8);
+                Action::Stepped
+            }
+            "reverse-continue" | "rc" => self.reverse_continue(chip8)?,
+            "regs" | "r" => {
+                print!("{}", registers(chip8));
+                Action::None
+            }
+            "stack" => {
+                if chip8.stack_pointer == 0 {
+                    println!("Stack is empty");
+                }
+                for (depth, addr) in chip8.stack[..chip8.stack_pointer.min(16)].iter().enumerate().rev() {
+                    println!("{:2}: {:03x}", depth, addr);
+                }
+                Action::None
+            }
+            "mem" | "x" => {
+                let start = arg(0)? & 0xFFF;
+                let len = if args.len() > 1 { arg(1)? } else { 64 };
+                let end = start.saturating_add(len).min(0x1000);
+                for (row, bytes) in chip8.memory[start..end].chunks(16).enumerate() {
+                    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                    println!("{:03x}: {}", start + row * 16, hex.join(" "));
+                }
+                Action::None
+            }
+            "disasm" | "l" => {
+                let addr = if args.is_empty() { chip8.reg_pc } else { arg(0)? & 0xFFF };
+                let n = if args.len() > 1 { arg(1)? } else { 10 };
+                // a few instructions before, assuming they're on the same alignment
+                let start = addr.saturating_sub(n / 2 * 2);
+                let end = start.saturating_add(n.saturating_mul(2)).min(0x1000);
+                for line in disassemble_range(&chip8.memory, start, end) {
+                    let marker = if line.starts_with(&format!("{:03x}:", chip8.reg_pc)) { "=>" } else { "  " };
+                    println!("{} {}", marker, line);
+                }
+                Action::None
+            }
+            "set" => {
+                let target = Target::parse(args.first().ok_or("set: missing register or [address]")?)?;
+                target.set(chip8, arg(1)?)?;
+                self.history.edited(chip8);
+                Action::None
+            }
+            "help" | "h" => {
+                print!("{}", HELP);
+                Action::None
+            }
+            "quit" | "q" => Action::Quit,
+            _ => return Err(format!("unknown command: {} (try help)", command)),
+        };
+        Ok(action)
+    }
+
+    fn step(&mut self, chip8: &mut Chip8, n: usize) -> Action {
+        for _ in 0..n {
+            chip8.cycle();
+            if self.check(chip8) {
+                break;
+            }
+        }
+        Action::Stepped
+    }
+
+    fn point(&mut self, id: usize) -> Result<&mut Point, String> {
+        self.points
+            .iter_mut()
+            .find(|p| p.id as usize == id)
+            .ok_or_else(|| format!("no breakpoint, watchpoint or catchpoint {}", id))
+    }
+}
+
+static EVENTS: [Event; 5] = [Event::Unknown, Event::Collision, Event::Key, Event::Sound, Event::LowWrite];
+
+fn parse_condition(words: &[&str]) -> Result<(String, Expr), String> {
+    let text = words.join(" ");
+    let expr = Expr::parse(&text)?;
+    Ok((text, expr))
+}
+
+// the instruction about to run, e.g. `204: 6012  LD V0, 0x12`
+pub fn location(chip8: &Chip8) -> String {
+    let mut line = format!("{:03x}: {:04x}  {}", chip8.reg_pc, chip8.opcode(), disassemble(chip8.opcode()));
+    if chip8.awaiting_keypress {
+        line.push_str("  (waiting for a key)");
+    } else if chip8.awaiting_vblank {
+        line.push_str("  (waiting for the display)");
+    }
+    line
+}
+
+pub fn registers(chip8: &Chip8) -> String {
+    let mut text = String::new();
+    for (row, regs) in chip8.regs_v.chunks(8).enumerate() {
+        let regs: Vec<String> = regs
+            .iter()
+            .enumerate()
+            .map(|(n, v)| format!("V{:X}={:02x}", row * 8 + n, v))
+            .collect();
+        text.push_str(&regs.join(" "));
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "I={:03x} PC={:03x} SP={} DT={:02x} ST={:02x}\n",
+        chip8.reg_i, chip8.reg_pc, chip8.stack_pointer, chip8.delay_timer, chip8.sound_timer
+    ));
+    text
+}
@@ -0,0 +1,107 @@
+// CHIP-8 mnemonics, following Cowgod's technical reference
+
+use crate::emu::{EXECUTED, MODIFIED_CODE, READ, WRITTEN};
+
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    let n = opcode & 0xF;
+    let kk = opcode & 0xFF;
+    let nnn = opcode & 0xFFF;
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS {:#05x}", nnn),
+        },
+        0x1 => format!("JP {:#05x}", nnn),
+        0x2 => format!("CALL {:#05x}", nnn),
+        0x3 => format!("SE V{:X}, {:#04x}", x, kk),
+        0x4 => format!("SNE V{:X}, {:#04x}", x, kk),
+        0x5 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6 => format!("LD V{:X}, {:#04x}", x, kk),
+        0x7 => format!("ADD V{:X}, {:#04x}", x, kk),
+        0x8 => {
+            let mnemonic = match n {
+                0x0 => "LD",
+                0x1 => "OR",
+                0x2 => "AND",
+                0x3 => "XOR",
+                0x4 => "ADD",
+                0x5 => "SUB",
+                0x6 => "SHR",
+                0x7 => "SUBN",
+                0xE => "SHL",
+                _ => return data_word(opcode),
+            };
+            format!("{} V{:X}, V{:X}", mnemonic, x, y)
+        }
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {:#05x}", nnn),
+        0xB => format!("JP V0, {:#05x}", nnn),
+        0xC => format!("RND V{:X}, {:#04x}", x, kk),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => data_word(opcode),
+        },
+        0xF => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => data_word(opcode),
+        },
+        _ => data_word(opcode),
+    }
+}
+
+fn data_word(opcode: u16) -> String {
+    format!("DW {:#06x}", opcode)
+}
+
+// one line per instruction: address, raw opcode, mnemonic
+pub fn disassemble_range(memory: &[u8], start: usize, end: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut addr = start;
+    while addr + 1 < end.min(memory.len()) {
+        let opcode = ((memory[addr] as u16) << 8) | memory[addr + 1] as u16;
+        lines.push(format!("{:03x}: {:04x}  {}", addr, opcode, disassemble(opcode)));
+        addr += 2;
+    }
+    lines
+}
+
+// Like `disassemble_range`, but only what ran (according to an access map)
+// is taken as instructions, wherever it starts; everything else is shown as
+// data bytes, with what was done with them.
+pub fn disassemble_mapped(memory: &[u8], access: &[u8], start: usize, end: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut addr = start;
+    let end = end.min(memory.len());
+    while addr < end {
+        let bits = access[addr];
+        let note = if bits & MODIFIED_CODE != 0 { "  ; modified" } else { "" };
+        if bits & EXECUTED != 0 && addr + 1 < end {
+            let opcode = ((memory[addr] as u16) << 8) | memory[addr + 1] as u16;
+            lines.push(format!("{:03x}: {:04x}  {}{}", addr, opcode, disassemble(opcode), note));
+            addr += 2;
+        } else {
+            let note = match bits & (READ | WRITTEN) {
+                0 => "",
+                READ => "  ; read",
+                WRITTEN => "  ; written",
+                _ => "  ; read, written",
+            };
+            lines.push(format!("{:03x}: {:02x}    DB {:#04x}{}", addr, memory[addr], memory[addr], note));
+            addr += 1;
+        }
+    }
+    lines
+}
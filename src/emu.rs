@@ -1,12 +1,60 @@
 use crate::font::FONT_DATA;
+use crate::framebuffer::Framebuffer;
 use crate::rng::RandomBytes;
 
+#[derive(Clone, Copy, Default)]
 pub struct Chip8Options {
     // enable for original chip8 compatibility
     pub saving_increases_reg_i: bool,
     pub shift_vy_not_vx: bool,
+    // DXYN waits for the next 60 Hz tick, like the VIP waiting for the display interrupt
+    pub display_wait: bool,
 }
 
+pub static QUIRK_NAMES: [&str; 3] = ["savingIncreasesRegI", "shiftVyNotVx", "displayWait"];
+
+pub static PRESET_NAMES: [&str; 2] = ["vip", "schip"];
+
+impl Chip8Options {
+    pub fn preset(name: &str) -> Option<Chip8Options> {
+        match name {
+            // the original COSMAC VIP interpreter
+            "vip" => Some(Chip8Options {
+                saving_increases_reg_i: true,
+                shift_vy_not_vx: true,
+                display_wait: true,
+            }),
+            // SUPER-CHIP on the HP48, which most later interpreters copied
+            "schip" => Some(Chip8Options::default()),
+            _ => None,
+        }
+    }
+    pub fn set_quirk(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        match name {
+            "savingIncreasesRegI" => self.saving_increases_reg_i = enabled,
+            "shiftVyNotVx" => self.shift_vy_not_vx = enabled,
+            "displayWait" => self.display_wait = enabled,
+            _ => return Err(format!("unknown quirk: {}", name)),
+        }
+        Ok(())
+    }
+    pub fn quirks_description(&self) -> String {
+        format!(
+            "savingIncreasesRegI={} shiftVyNotVx={} displayWait={}",
+            self.saving_increases_reg_i, self.shift_vy_not_vx, self.display_wait
+        )
+    }
+}
+
+// what an address has been used for, as bits of `Chip8::access`
+pub const EXECUTED: u8 = 1;
+pub const READ: u8 = 2;
+pub const WRITTEN: u8 = 4;
+// written after it ran, or run after it was written: self-modifying code
+pub const MODIFIED_CODE: u8 = 8;
+
+// Clone gives a complete snapshot of the machine, including any pending waits
+#[derive(Clone)]
 pub struct Chip8 {
     pub memory: [u8; 4096],
     pub reg_pc: usize,
@@ -16,11 +64,18 @@ pub struct Chip8 {
     pub sound_timer: u8,
     pub stack: [usize; 16],
     pub stack_pointer: usize,
-    pub display: [[usize; 64]; 32], // needs to be public
+    pub display: Framebuffer, // needs to be public
     pub rng: RandomBytes,
     pub keys: [bool; 16], // needs to be public
     pub awaiting_keypress: bool,
     pub register_awaiting_keypress: usize,
+    pub awaiting_vblank: bool,
+    // calls to `cycle` (waiting included) and to `tick` so far, for the
+    // debugger's history
+    pub cycles: u64,
+    pub ticks: u64,
+    // how each address has been used, once `record_access` has been called
+    pub access: Option<Vec<u8>>,
     options: Chip8Options, //nice_counter: usize
 }
 
@@ -38,15 +93,37 @@ impl Chip8 {
             sound_timer: 0,
             stack: [0; 16],
             stack_pointer: 0,
-            display: [[0; 64]; 32],
+            display: Framebuffer::new(64, 32, 1),
             rng: RandomBytes::new(),
             keys: [false; 16],
             awaiting_keypress: false,
             register_awaiting_keypress: 0,
+            awaiting_vblank: false,
+            cycles: 0,
+            ticks: 0,
+            access: None,
             options: options, //nice_counter: 0
         }
     }
+    // starts keeping `access` up to date (which costs a little speed)
+    pub fn record_access(&mut self) {
+        self.access = Some(vec![0; 0x1000]);
+    }
+    fn mark(&mut self, addr: usize, len: usize, how: u8) {
+        if let Some(access) = self.access.as_mut() {
+            for addr in addr..addr + len {
+                let bits = &mut access[addr & 0xFFF];
+                let other = if how == EXECUTED { WRITTEN } else { EXECUTED };
+                if how != READ && *bits & other != 0 {
+                    *bits |= MODIFIED_CODE;
+                }
+                *bits |= how;
+            }
+        }
+    }
     pub fn tick(&mut self) {
+        self.ticks += 1;
+        self.awaiting_vblank = false;
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -54,7 +131,17 @@ impl Chip8 {
             self.sound_timer -= 1;
         }
     }
-    pub fn cycle(&mut self) {
+    // the instruction at PC, which `cycle` runs next
+    pub fn opcode(&self) -> u16 {
+        u16::from_be_bytes([self.memory[self.reg_pc & 0xFFF], self.memory[(self.reg_pc + 1) & 0xFFF]])
+    }
+    // returns what the instruction would have cost on the COSMAC VIP, in machine cycles
+    pub fn cycle(&mut self) -> u32 {
+        self.cycles += 1;
+        if self.awaiting_vblank {
+            // nothing runs until the next tick
+            return 0;
+        }
         if self.awaiting_keypress {
             for i in 0..=0xF {
                 if self.keys[i] {
@@ -64,9 +151,11 @@ impl Chip8 {
                     break;
                 }
             }
-            return;
+            // the VIP polls the keypad in a loop
+            return vip_cycles(0xF00A);
         }
 
+        self.mark(self.reg_pc, 2, EXECUTED);
         let first_byte = self.memory[self.reg_pc];
         let second_byte = self.memory[self.reg_pc + 1];
         let first_nibble = first_byte >> 4;
@@ -183,7 +272,12 @@ impl Chip8 {
                         let rnd_byte = self.rng.next();
                         vx_set!(rnd_byte & kk);
                     }
-                    0xD => self.draw_sprite(vx!(), vy!(), fourth_nibble),
+                    0xD => {
+                        self.draw_sprite(vx!(), vy!(), fourth_nibble);
+                        if self.options.display_wait {
+                            self.awaiting_vblank = true;
+                        }
+                    }
                     0xF => {
                         let vx = vx!();
                         match second_byte {
@@ -208,11 +302,14 @@ impl Chip8 {
                                 self.reg_i = index;
                             }
                             0x33 => {
+                                // recorded as read as well as written, so the digits count as data
+                                self.mark(self.reg_i, 3, READ | WRITTEN);
                                 self.memory[self.reg_i + 0] = vx / 100;
                                 self.memory[self.reg_i + 1] = (vx / 10) % 10;
                                 self.memory[self.reg_i + 2] = vx % 10;
                             }
                             0x55 => {
+                                self.mark(self.reg_i, x as usize + 1, WRITTEN);
                                 for i in 0..=(x as usize) {
                                     self.memory[self.reg_i + i] = self.regs_v[i];
                                 }
@@ -221,6 +318,7 @@ impl Chip8 {
                                 }
                             }
                             0x65 => {
+                                self.mark(self.reg_i, x as usize + 1, READ);
                                 for i in 0..=(x as usize) {
                                     self.regs_v[i] = self.memory[self.reg_i + i];
                                 }
@@ -236,9 +334,10 @@ impl Chip8 {
                 self.reg_pc += 2;
             }
         }
+        vip_cycles(((first_byte as u16) << 8) | second_byte as u16)
     }
     fn clear_display(&mut self) {
-        self.display = [[0; 64]; 32];
+        self.display.clear();
     }
     fn call_subroutine(&mut self, addr: usize) {
         if self.stack_pointer >= 16 {
@@ -278,19 +377,18 @@ impl Chip8 {
         let x = x as usize;
         let y = y as usize;
         let height = height as usize;
+        let width = self.display.width();
+        let display_height = self.display.height();
+        self.mark(self.reg_i, height, READ);
         for src_y in 0..height {
             let pixel_row = self.memory[self.reg_i + src_y] as usize;
-            let disp_y = (y + src_y) % 32;
+            let disp_y = (y + src_y) % display_height;
             for src_x in 0..8 {
                 let is_set = (pixel_row >> (7 - src_x)) & 1;
                 if is_set == 1 {
-                    let disp_x = (x + src_x) % 64;
-                    let to_set = &mut self.display[disp_y][disp_x];
-                    if *to_set == 1 {
+                    let disp_x = (x + src_x) % width;
+                    if self.display.toggle(0, disp_x, disp_y) {
                         one_or_more_erased = true;
-                        *to_set = 0;
-                    } else {
-                        *to_set = 1;
                     }
                 }
             }
@@ -329,6 +427,57 @@ impl Chip8 {
     }
 }
 
+// whether `cycle` knows what to do with an opcode (rather than warning about it)
+pub fn is_implemented(opcode: u16) -> bool {
+    match opcode >> 12 {
+        0x0 => opcode == 0x00E0 || opcode == 0x00EE,
+        0x8 => matches!(opcode & 0xF, 0x0..=0x7 | 0xE),
+        0xF => matches!(opcode & 0xFF, 0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x33 | 0x55 | 0x65),
+        _ => true,
+    }
+}
+
+// Approximate cost of each instruction in the COSMAC VIP interpreter, in
+// machine cycles (8 clocks; the 1802 runs most instructions in 2). The real
+// costs also vary a little with operands (skips taken, carries, sprite
+// alignment); only the sprite height and register count are modelled, since
+// those dominate.
+pub fn vip_cycles(opcode: u16) -> u32 {
+    // fetching and dispatching the instruction
+    const FETCH: u32 = 40;
+    let x = ((opcode >> 8) & 0xF) as u32;
+    let n = (opcode & 0xF) as u32;
+    let execute = match opcode >> 12 {
+        0x0 => match opcode {
+            // a loop over all 256 bytes of display memory
+            0x00E0 => 24 + 256 * 6,
+            _ => 10,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 | 0x4 => 10,
+        0x5 | 0x9 => 14,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        // each sprite row is shifted into place and XORed into two display bytes
+        0xD => 26 + n * 84,
+        0xE => 14,
+        0xF => match opcode & 0xFF {
+            0x0A => 19,
+            0x1E | 0x29 => 16,
+            0x33 => 150,
+            0x55 | 0x65 => 14 + 14 * (x + 1),
+            _ => 10,
+        },
+        _ => 0,
+    };
+    FETCH + execute
+}
+
 #[inline(always)]
 fn add_get_carry(a: u8, b: u8) -> (u8, u8) {
     let (sum, carry) = a.overflowing_add(b);
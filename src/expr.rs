@@ -0,0 +1,189 @@
+use crate::debugger::{parse_number, Target};
+use crate::emu::Chip8;
+
+// Expressions over the machine's state, for breakpoint conditions, e.g.
+//
+//   v3 == 0x10 && [i] != 0
+//
+// Registers are v0-vf, i, pc, sp, dt and st; [expr] is the memory byte at an
+// address. Operators are as in C, without assignment or multiplication.
+// Comparisons and logical operators give 1 or 0, and anything non-zero is true.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Num(usize),
+    Reg(Target),
+    Mem(Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+}
+
+// lowest precedence first
+static OPS: [&[(&str, Op)]; 8] = [
+    &[("||", Op::Or)],
+    &[("&&", Op::And)],
+    &[("|", Op::BitOr)],
+    &[("^", Op::BitXor)],
+    &[("&", Op::BitAnd)],
+    &[("==", Op::Eq), ("!=", Op::Ne)],
+    &[("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)],
+    &[("+", Op::Add), ("-", Op::Sub)],
+];
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Expr, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.binary(0)?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {} in expression", token)),
+        }
+    }
+
+    pub fn eval(&self, chip8: &Chip8) -> usize {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Reg(target) => target.get(chip8),
+            Expr::Mem(addr) => chip8.memory[addr.eval(chip8) & 0xFFF] as usize,
+            Expr::Not(e) => (e.eval(chip8) == 0) as usize,
+            Expr::Neg(e) => e.eval(chip8).wrapping_neg(),
+            Expr::Binary(op, a, b) => {
+                let a = a.eval(chip8);
+                // && and || only look at the right side when they need to
+                match op {
+                    Op::Or if a != 0 => return 1,
+                    Op::And if a == 0 => return 0,
+                    _ => {}
+                }
+                let b = b.eval(chip8);
+                match op {
+                    Op::Or | Op::And => (b != 0) as usize,
+                    Op::BitOr => a | b,
+                    Op::BitXor => a ^ b,
+                    Op::BitAnd => a & b,
+                    Op::Eq => (a == b) as usize,
+                    Op::Ne => (a != b) as usize,
+                    Op::Lt => (a < b) as usize,
+                    Op::Le => (a <= b) as usize,
+                    Op::Gt => (a > b) as usize,
+                    Op::Ge => (a >= b) as usize,
+                    Op::Add => a.wrapping_add(b),
+                    Op::Sub => a.wrapping_sub(b),
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, chip8: &Chip8) -> bool {
+        self.eval(chip8) != 0
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = s.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["||", "&&", "==", "!=", "<=", ">="].contains(&&two[..]) {
+                tokens.push(two);
+                i += 2;
+            } else if "|&^<>+-!()[]".contains(c) {
+                tokens.push(c.to_string());
+                i += 1;
+            } else {
+                return Err(format!("unexpected {} in expression", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected {} but found {} in expression", expected, token)),
+            None => Err(format!("expected {} at the end of the expression", expected)),
+        }
+    }
+
+    // operators at `level` of OPS or higher, left to right
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == OPS.len() {
+            return self.unary();
+        }
+        let mut expr = self.binary(level + 1)?;
+        while let Some(&(_, op)) = self
+            .tokens
+            .get(self.pos)
+            .and_then(|token| OPS[level].iter().find(|(s, _)| s == token))
+        {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("incomplete expression")?;
+        match &token[..] {
+            "!" => Ok(Expr::Not(Box::new(self.unary()?))),
+            "-" => Ok(Expr::Neg(Box::new(self.unary()?))),
+            "(" => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "[" => {
+                let addr = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Mem(Box::new(addr)))
+            }
+            _ if token.starts_with(|c: char| c.is_ascii_digit()) => Ok(Expr::Num(parse_number(&token)?)),
+            _ => Target::parse(&token)
+                .map(Expr::Reg)
+                .map_err(|_| format!("unknown register {} in expression", token)),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::palette::blend;
+
+// Software upscalers, run on the screen's colours before they go anywhere, so
+// every output looks the same. Each scales by a whole factor; stretching to
+// the final size is left to whoever shows the result.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    // plain square pixels
+    None,
+    // Scale2x (also known as EPX/AdvMAME2x): rounds off diagonal edges
+    Scale2x,
+    Scale3x,
+    // every third line darkened, like the gaps between a CRT's scanlines
+    Scanlines,
+}
+
+pub static FILTER_NAMES: [&str; 4] = ["none", "scale2x", "scale3x", "scanlines"];
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "none" => Some(Filter::None),
+            "scale2x" | "epx" => Some(Filter::Scale2x),
+            "scale3x" => Some(Filter::Scale3x),
+            "scanlines" => Some(Filter::Scanlines),
+            _ => None,
+        }
+    }
+
+    pub fn scale(&self) -> usize {
+        match self {
+            Filter::None => 1,
+            Filter::Scale2x => 2,
+            Filter::Scale3x | Filter::Scanlines => 3,
+        }
+    }
+
+    // `src` is width x height colours, row by row; the result is scale() times
+    // as wide and high
+    pub fn apply(&self, src: &[u32], width: usize, height: usize) -> Vec<u32> {
+        let scale = self.scale();
+        let out_width = width * scale;
+        let mut out = vec![0; out_width * height * scale];
+        // neighbours past the edge are taken to be the edge pixel
+        let at = |x: usize, y: usize, dx: isize, dy: isize| {
+            let x = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+            let y = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+            src[y * width + x]
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let block = match self {
+                    Filter::None => vec![src[y * width + x]],
+                    Filter::Scale2x => scale2x(|dx, dy| at(x, y, dx, dy)).to_vec(),
+                    Filter::Scale3x => scale3x(|dx, dy| at(x, y, dx, dy)).to_vec(),
+                    Filter::Scanlines => {
+                        let e = src[y * width + x];
+                        let dark = blend(e, 0, 96);
+                        vec![e, e, e, e, e, e, dark, dark, dark]
+                    }
+                };
+                for (i, &color) in block.iter().enumerate() {
+                    out[(y * scale + i / scale) * out_width + x * scale + i % scale] = color;
+                }
+            }
+        }
+        out
+    }
+}
+
+//  B
+// DEF  ->  E0 E1
+//  H       E2 E3
+fn scale2x(at: impl Fn(isize, isize) -> u32) -> [u32; 4] {
+    let (b, d, e, f, h) = (at(0, -1), at(-1, 0), at(0, 0), at(1, 0), at(0, 1));
+    if b == h || d == f {
+        return [e; 4];
+    }
+    [
+        if d == b { d } else { e },
+        if b == f { f } else { e },
+        if d == h { d } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+// ABC      E0 E1 E2
+// DEF  ->  E3 E4 E5
+// GHI      E6 E7 E8
+fn scale3x(at: impl Fn(isize, isize) -> u32) -> [u32; 9] {
+    let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+    let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+    let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+    if b == h || d == f {
+        return [e; 9];
+    }
+    [
+        if d == b { d } else { e },
+        if (d == b && e != c) || (b == f && e != a) { b } else { e },
+        if b == f { f } else { e },
+        if (d == b && e != g) || (d == h && e != a) { d } else { e },
+        e,
+        if (b == f && e != i) || (h == f && e != c) { f } else { e },
+        if d == h { d } else { e },
+        if (d == h && e != i) || (h == f && e != g) { h } else { e },
+        if h == f { f } else { e },
+    ]
+}
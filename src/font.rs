@@ -10,4 +10,56 @@ pub static FONT_DATA: [u8; 80] = [
     16,  96,  96, 160, 224, 160, 160, 192, 160, 224, 160, 192,
    112, 128, 128, 128, 112,  96,  80,  80,  80,  96, 112, 128,
    240, 128, 112, 112,  64, 112,  64,  64
-];
\ No newline at end of file
+];
+
+// The rest of the letters, and some punctuation, in the same 4x5 style, for
+// the debug overlay's text. Each row's pixels are the top 4 bits.
+static TEXT_DATA: [(char, [u8; 5]); 33] = [
+    ('G', [0x70, 0x80, 0xB0, 0x90, 0x70]),
+    ('H', [0x90, 0x90, 0xF0, 0x90, 0x90]),
+    ('I', [0xE0, 0x40, 0x40, 0x40, 0xE0]),
+    ('J', [0x30, 0x10, 0x10, 0x90, 0x60]),
+    ('K', [0x90, 0xA0, 0xC0, 0xA0, 0x90]),
+    ('L', [0x80, 0x80, 0x80, 0x80, 0xF0]),
+    ('M', [0x90, 0xF0, 0xF0, 0x90, 0x90]),
+    ('N', [0x90, 0xD0, 0xB0, 0x90, 0x90]),
+    ('O', [0x60, 0x90, 0x90, 0x90, 0x60]),
+    ('P', [0xE0, 0x90, 0xE0, 0x80, 0x80]),
+    ('Q', [0x60, 0x90, 0x90, 0xA0, 0x50]),
+    ('R', [0xE0, 0x90, 0xE0, 0xA0, 0x90]),
+    ('S', [0x70, 0x80, 0x60, 0x10, 0xE0]),
+    ('T', [0xE0, 0x40, 0x40, 0x40, 0x40]),
+    ('U', [0x90, 0x90, 0x90, 0x90, 0x60]),
+    ('V', [0x90, 0x90, 0x90, 0x60, 0x60]),
+    ('W', [0x90, 0x90, 0xF0, 0xF0, 0x90]),
+    ('X', [0x90, 0x90, 0x60, 0x90, 0x90]),
+    ('Y', [0xA0, 0xA0, 0x40, 0x40, 0x40]),
+    ('Z', [0xF0, 0x10, 0x60, 0x80, 0xF0]),
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x40]),
+    (',', [0x00, 0x00, 0x00, 0x40, 0x80]),
+    (':', [0x00, 0x40, 0x00, 0x40, 0x00]),
+    ('-', [0x00, 0x00, 0xF0, 0x00, 0x00]),
+    ('=', [0x00, 0xF0, 0x00, 0xF0, 0x00]),
+    ('+', [0x00, 0x40, 0xE0, 0x40, 0x00]),
+    ('>', [0x80, 0x40, 0x20, 0x40, 0x80]),
+    ('[', [0x60, 0x40, 0x40, 0x40, 0x60]),
+    (']', [0x60, 0x20, 0x20, 0x20, 0x60]),
+    ('(', [0x20, 0x40, 0x40, 0x40, 0x20]),
+    (')', [0x40, 0x20, 0x20, 0x20, 0x40]),
+    ('/', [0x10, 0x10, 0x20, 0x40, 0x80]),
+];
+
+// shown for anything without a glyph
+const UNKNOWN: [u8; 5] = [0xE0, 0x20, 0x60, 0x00, 0x40];
+
+// A character's glyph, from the hex digits for 0-9 and A-F; letters in
+// either case.
+pub fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    if let Some(digit) = c.to_digit(16) {
+        let start = digit as usize * 5;
+        return FONT_DATA[start..start + 5].try_into().unwrap();
+    }
+    TEXT_DATA.iter().find(|&&(t, _)| t == c).map_or(UNKNOWN, |&(_, rows)| rows)
+}
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// The display: one bit per pixel, packed into 64-bit words, with one or more
+// planes (XO-CHIP style; each pixel's value is the set of planes it is lit
+// in, which a palette turns into a colour). Bit 63 of a row's first word is
+// x = 0.
+//
+// Each row is stamped when it changes, so a frontend that remembers the
+// newest stamp it has drawn only has to redraw rows changed since. Stamps
+// come from one counter for every framebuffer, so a snapshot put back in
+// place of the display (then marked dirty) can't look already drawn.
+static STAMPS: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: usize,
+    words_per_row: usize,
+    // plane by plane, row by row
+    bits: Vec<u64>,
+    // per row, the stamp of its last change
+    stamps: Vec<u64>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize, planes: usize) -> Framebuffer {
+        let words_per_row = width.div_ceil(64);
+        let mut fb = Framebuffer {
+            width,
+            height,
+            planes,
+            words_per_row,
+            bits: vec![0; words_per_row * height * planes],
+            stamps: vec![0; height],
+        };
+        // nothing has been drawn by a frontend yet
+        fb.mark_dirty();
+        fb
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn index(&self, plane: usize, x: usize, y: usize) -> (usize, u64) {
+        let word = (plane * self.height + y) * self.words_per_row + x / 64;
+        (word, 1 << (63 - x % 64))
+    }
+
+    // plane 0, for single plane displays (only the tests still look at one)
+    #[allow(dead_code)]
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.get_plane(0, x, y)
+    }
+
+    pub fn get_plane(&self, plane: usize, x: usize, y: usize) -> bool {
+        let (word, mask) = self.index(plane, x, y);
+        self.bits[word] & mask != 0
+    }
+
+    // bit n set if the pixel is lit in plane n
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        (0..self.planes).fold(0, |p, plane| p | ((self.get_plane(plane, x, y) as u8) << plane))
+    }
+
+    // XORs a pixel on, returning true if it was already lit (a collision)
+    pub fn toggle(&mut self, plane: usize, x: usize, y: usize) -> bool {
+        let (word, mask) = self.index(plane, x, y);
+        self.bits[word] ^= mask;
+        self.mark_row_dirty(y);
+        self.bits[word] & mask == 0
+    }
+
+    pub fn clear(&mut self) {
+        if self.bits.iter().any(|&w| w != 0) {
+            self.bits.iter_mut().for_each(|w| *w = 0);
+            self.mark_dirty();
+        }
+    }
+
+    // (x, y, pixel) in row order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        (0..self.height)
+            .flat_map(move |y| (0..self.width).map(move |x| (x, y, self.pixel(x, y))))
+    }
+
+    // the newest stamp, to pass to `rows_changed_since` later
+    pub fn stamp(&self) -> u64 {
+        self.stamps.iter().copied().max().unwrap_or(0)
+    }
+
+    fn is_row_changed_since(&self, y: usize, stamp: u64) -> bool {
+        self.stamps[y] > stamp
+    }
+
+    pub fn rows_changed_since(&self, stamp: u64) -> impl Iterator<Item = usize> + '_ {
+        (0..self.height).filter(move |&y| self.is_row_changed_since(y, stamp))
+    }
+
+    fn mark_row_dirty(&mut self, y: usize) {
+        self.stamps[y] = STAMPS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // forces a full redraw, e.g. after the display is replaced by a snapshot
+    pub fn mark_dirty(&mut self) {
+        let stamp = STAMPS.fetch_add(1, Ordering::Relaxed);
+        self.stamps.iter_mut().for_each(|s| *s = stamp);
+    }
+}
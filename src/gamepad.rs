@@ -0,0 +1,260 @@
+use crate::config::Config;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+use std::collections::{HashMap, HashSet};
+
+// how far a stick or trigger has to move before it counts as pressed
+const AXIS_THRESHOLD: i16 = 16384;
+
+// Controller inputs that can be bound to CHIP-8 keys. The left stick drives
+// the same four directions as the D-pad.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PadInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    X,
+    Y,
+    Back,
+    Start,
+    LeftShoulder,
+    RightShoulder,
+    LeftTrigger,
+    RightTrigger,
+}
+
+static INPUT_NAMES: [(&str, PadInput); 14] = [
+    ("up", PadInput::Up),
+    ("down", PadInput::Down),
+    ("left", PadInput::Left),
+    ("right", PadInput::Right),
+    ("a", PadInput::A),
+    ("b", PadInput::B),
+    ("x", PadInput::X),
+    ("y", PadInput::Y),
+    ("back", PadInput::Back),
+    ("start", PadInput::Start),
+    ("leftshoulder", PadInput::LeftShoulder),
+    ("rightshoulder", PadInput::RightShoulder),
+    ("lefttrigger", PadInput::LeftTrigger),
+    ("righttrigger", PadInput::RightTrigger),
+];
+
+impl PadInput {
+    pub fn from_name(name: &str) -> Option<PadInput> {
+        INPUT_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, input)| *input)
+    }
+}
+
+pub struct PadMap {
+    bindings: HashMap<PadInput, u8>,
+}
+
+impl Default for PadMap {
+    // 2/4/6/8 are the arrow keys in most games, 5 is usually fire
+    fn default() -> PadMap {
+        let mut map = PadMap {
+            bindings: HashMap::new(),
+        };
+        map.bind(PadInput::Up, 0x2);
+        map.bind(PadInput::Down, 0x8);
+        map.bind(PadInput::Left, 0x4);
+        map.bind(PadInput::Right, 0x6);
+        map.bind(PadInput::A, 0x5);
+        map.bind(PadInput::B, 0x0);
+        map
+    }
+}
+
+impl PadMap {
+    // Bindings are layered: the defaults, then entries before any section
+    // (which apply to every ROM), then the key hints from the ROM's profile,
+    // then a section named after the ROM file (without extension):
+    //
+    //   a = 5
+    //   [BRIX]
+    //   left = 4
+    //   right = 6
+    //   up = none
+    pub fn from_config(
+        config: &Config,
+        rom_name: &str,
+        hints: &[(&str, u8)],
+    ) -> Result<PadMap, String> {
+        let mut map = PadMap::default();
+        map.apply_config(config, "")?;
+        map.apply_hints(hints);
+        map.apply_config(config, rom_name)?;
+        Ok(map)
+    }
+
+    // the user's gamepad.cfg if there is one, else the defaults
+    pub fn load(rom_name: &str, hints: &[(&str, u8)]) -> Result<PadMap, String> {
+        match crate::config::config_dir().map(|dir| dir.join("gamepad.cfg")) {
+            Some(path) if path.exists() => {
+                let config = Config::load(&path)?;
+                PadMap::from_config(&config, rom_name, hints)
+                    .map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            _ => {
+                let mut map = PadMap::default();
+                map.apply_hints(hints);
+                Ok(map)
+            }
+        }
+    }
+
+    fn apply_config(&mut self, config: &Config, section: &str) -> Result<(), String> {
+        for entry in config.section(section) {
+            let input = PadInput::from_name(&entry.key).ok_or_else(|| {
+                format!("line {}: unknown controller input: {}", entry.line, entry.key)
+            })?;
+            if entry.value == "none" {
+                self.unbind(input);
+                continue;
+            }
+            match u8::from_str_radix(entry.value.trim_start_matches("0x"), 16) {
+                Ok(k) if k <= 0xF => self.bind(input, k),
+                _ => {
+                    return Err(format!(
+                        "line {}: not a CHIP-8 key: {}",
+                        entry.line, entry.value
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Hints name the keys a game actually uses, so the default bindings for
+    // the directions and buttons they don't mention are dropped.
+    fn apply_hints(&mut self, hints: &[(&str, u8)]) {
+        if hints.is_empty() {
+            return;
+        }
+        self.bindings.clear();
+        for (name, key) in hints {
+            if let Some(input) = PadInput::from_name(name) {
+                self.bind(input, *key);
+            }
+        }
+    }
+
+    pub fn bind(&mut self, input: PadInput, chip8_key: u8) {
+        self.bindings.insert(input, chip8_key);
+    }
+
+    pub fn unbind(&mut self, input: PadInput) {
+        self.bindings.remove(&input);
+    }
+
+    pub fn get(&self, input: PadInput) -> Option<u8> {
+        self.bindings.get(&input).copied()
+    }
+}
+
+// A physical control on a particular controller. The D-pad and the stick are
+// tracked separately so that letting go of one doesn't cancel the other.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Source {
+    Button(Button),
+    // axis, positive direction
+    Axis(Axis, bool),
+}
+
+impl Source {
+    fn input(self) -> Option<PadInput> {
+        let input = match self {
+            Source::Button(Button::DPadUp) | Source::Axis(Axis::LeftY, false) => PadInput::Up,
+            Source::Button(Button::DPadDown) | Source::Axis(Axis::LeftY, true) => PadInput::Down,
+            Source::Button(Button::DPadLeft) | Source::Axis(Axis::LeftX, false) => PadInput::Left,
+            Source::Button(Button::DPadRight) | Source::Axis(Axis::LeftX, true) => PadInput::Right,
+            Source::Button(Button::A) => PadInput::A,
+            Source::Button(Button::B) => PadInput::B,
+            Source::Button(Button::X) => PadInput::X,
+            Source::Button(Button::Y) => PadInput::Y,
+            Source::Button(Button::Back) => PadInput::Back,
+            Source::Button(Button::Start) => PadInput::Start,
+            Source::Button(Button::LeftShoulder) => PadInput::LeftShoulder,
+            Source::Button(Button::RightShoulder) => PadInput::RightShoulder,
+            Source::Axis(Axis::TriggerLeft, true) => PadInput::LeftTrigger,
+            Source::Axis(Axis::TriggerRight, true) => PadInput::RightTrigger,
+            _ => return None,
+        };
+        Some(input)
+    }
+}
+
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    // open controllers by joystick instance id
+    controllers: HashMap<u32, GameController>,
+    map: PadMap,
+    held: HashSet<(u32, Source)>,
+}
+
+impl Gamepads {
+    // SDL reports controllers that are already plugged in as ControllerDeviceAdded
+    // events on startup, so they are opened the same way as hot-plugged ones.
+    pub fn new(subsystem: GameControllerSubsystem, map: PadMap) -> Gamepads {
+        Gamepads {
+            subsystem,
+            controllers: HashMap::new(),
+            map,
+            held: HashSet::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    eprintln!("Controller connected: {}", controller.name());
+                    self.controllers.insert(controller.instance_id(), controller);
+                }
+                Err(e) => eprintln!("Error opening controller {}: {}", which, e),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(controller) = self.controllers.remove(&which) {
+                    eprintln!("Controller disconnected: {}", controller.name());
+                }
+                self.held.retain(|(id, _)| *id != which);
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.held.insert((which, Source::Button(button)));
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.held.remove(&(which, Source::Button(button)));
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                self.held.remove(&(which, Source::Axis(axis, false)));
+                self.held.remove(&(which, Source::Axis(axis, true)));
+                if value > AXIS_THRESHOLD {
+                    self.held.insert((which, Source::Axis(axis, true)));
+                } else if value < -AXIS_THRESHOLD {
+                    self.held.insert((which, Source::Axis(axis, false)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn pressed(&self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (_, source) in &self.held {
+            if let Some(k) = source.input().and_then(|input| self.map.get(input)) {
+                keys[k as usize] = true;
+            }
+        }
+        keys
+    }
+}
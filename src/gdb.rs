@@ -0,0 +1,403 @@
+use crate::debugger::Target;
+use crate::emu::Chip8;
+use std::collections::BTreeSet;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// A stub for GDB's remote serial protocol, so debugger front-ends can drive
+// the emulator (`target remote localhost:<port>`). Everything is polled from
+// the frontend's loop rather than run on a thread, so the machine is only
+// ever touched between instructions.
+//
+// Registers, in GDB's numbering: V0-VF (8 bits), I, PC (16 bits), SP (the
+// stack depth), DT and ST (8 bits). Memory is the 4 KiB address space.
+pub struct GdbServer {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    // bytes received but not yet handled
+    input: Vec<u8>,
+    running: bool,
+    breakpoints: BTreeSet<usize>,
+    // write watchpoints: address, length, and the bytes there when last checked
+    watchpoints: Vec<(usize, usize, Vec<u8>)>,
+    // Whether the machine was waiting (for a key or the display) at the last
+    // check, so the cycle since ran no instruction. As in `Debugger`, a
+    // breakpoint fires each time an instruction arrives at it, even a jump to
+    // itself, but not on every cycle the machine waits there.
+    waiting: bool,
+}
+
+static REGISTERS: [Target; 21] = [
+    Target::V(0x0),
+    Target::V(0x1),
+    Target::V(0x2),
+    Target::V(0x3),
+    Target::V(0x4),
+    Target::V(0x5),
+    Target::V(0x6),
+    Target::V(0x7),
+    Target::V(0x8),
+    Target::V(0x9),
+    Target::V(0xA),
+    Target::V(0xB),
+    Target::V(0xC),
+    Target::V(0xD),
+    Target::V(0xE),
+    Target::V(0xF),
+    Target::I,
+    Target::Pc,
+    Target::Sp,
+    Target::Dt,
+    Target::St,
+];
+
+// signals for stop replies
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+fn register_size(target: Target) -> usize {
+    match target {
+        Target::I | Target::Pc => 2,
+        _ => 1,
+    }
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">",
+    );
+    for target in REGISTERS.iter() {
+        let size = register_size(*target) * 8;
+        let kind = match target {
+            Target::Pc => "code_ptr".to_string(),
+            Target::I => "data_ptr".to_string(),
+            _ => format!("uint{}", size),
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>",
+            target.to_string().to_ascii_lowercase(),
+            size,
+            kind
+        ));
+    }
+    xml.push_str("</feature></target>");
+    xml
+}
+
+impl GdbServer {
+    // listens on localhost only: the protocol has no authentication
+    pub fn new(port: u16) -> Result<GdbServer, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("port {}: {}", port, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(GdbServer {
+            listener,
+            stream: None,
+            input: Vec::new(),
+            // the game runs until GDB attaches
+            running: true,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            waiting: false,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|a| a.port()).unwrap_or(0)
+    }
+
+    // whether GDB has let the machine run
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    // accepts a connection and handles whatever GDB has sent
+    pub fn poll(&mut self, chip8: &mut Chip8) {
+        if self.stream.is_none() {
+            if let Ok((stream, addr)) = self.listener.accept() {
+                println!("GDB connected from {}", addr);
+                if stream.set_nonblocking(true).is_ok() {
+                    self.stream = Some(stream);
+                    self.input.clear();
+                    // the machine stops when GDB attaches
+                    self.running = false;
+                }
+            }
+        }
+        let mut buf = [0; 4096];
+        while let Some(stream) = self.stream.as_mut() {
+            match stream.read(&mut buf) {
+                Ok(0) => self.disconnect(),
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.disconnect(),
+            }
+        }
+        while let Some(packet) = self.next_packet() {
+            self.handle(chip8, &packet);
+        }
+    }
+
+    // stops the machine on request from the frontend (its pause key)
+    pub fn interrupt(&mut self) {
+        if self.running {
+            self.running = false;
+            self.send(&format!("S{:02x}", SIGINT));
+        }
+    }
+
+    // Called after every instruction while running; stops and tells GDB when
+    // a breakpoint or watchpoint is hit.
+    pub fn check(&mut self, chip8: &Chip8) -> bool {
+        let mut reply = None;
+        if self.breakpoints.contains(&chip8.reg_pc) && !self.waiting {
+            reply = Some(format!("S{:02x}", SIGTRAP));
+        }
+        for (addr, len, old) in self.watchpoints.iter_mut() {
+            let now = &chip8.memory[*addr..*addr + *len];
+            if now != &old[..] {
+                old.copy_from_slice(now);
+                reply = Some(format!("T{:02x}watch:{:x};", SIGTRAP, addr));
+            }
+        }
+        self.waiting = waiting(chip8);
+        match reply {
+            Some(reply) => {
+                self.running = false;
+                self.send(&reply);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if self.stream.take().is_some() {
+            println!("GDB disconnected");
+        }
+        // leave the game running for whoever is watching the window
+        self.running = true;
+    }
+
+    // the next complete packet's contents, acknowledging it
+    fn next_packet(&mut self) -> Option<String> {
+        loop {
+            let start = self.input.iter().position(|&b| b == b'$' || b == 0x03)?;
+            if self.input[start] == 0x03 {
+                // Ctrl-C from GDB
+                self.input.drain(..=start);
+                self.interrupt();
+                continue;
+            }
+            let end = self.input[start..].iter().position(|&b| b == b'#')? + start;
+            if self.input.len() < end + 3 {
+                return None;
+            }
+            let data = self.input[start + 1..end].to_vec();
+            let checksum = std::str::from_utf8(&self.input[end + 1..end + 3])
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            self.input.drain(..end + 3);
+            if checksum != Some(data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))) {
+                self.write(b"-");
+                continue;
+            }
+            self.write(b"+");
+            return Some(String::from_utf8_lossy(&data).into_owned());
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        self.write(format!("${}#{:02x}", data, checksum).as_bytes());
+    }
+
+    fn write(&mut self, mut data: &[u8]) {
+        while let Some(stream) = self.stream.as_mut() {
+            if data.is_empty() {
+                return;
+            }
+            match stream.write(data) {
+                Ok(n) => data = &data[n..],
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(std::time::Duration::from_millis(1)),
+                Err(_) => self.disconnect(),
+            }
+        }
+    }
+
+    fn handle(&mut self, chip8: &mut Chip8, packet: &str) {
+        let reply = match self.reply(chip8, packet) {
+            Ok(Some(reply)) => reply,
+            // the machine is running: the reply comes when it stops
+            Ok(None) => return,
+            Err(code) => format!("E{:02x}", code),
+        };
+        self.send(&reply);
+    }
+
+    fn reply(&mut self, chip8: &mut Chip8, packet: &str) -> Result<Option<String>, u8> {
+        // everything is ASCII, so it can be cut up by byte offsets below
+        if !packet.is_ascii() {
+            return Err(1);
+        }
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => REGISTERS.iter().map(|&r| register_hex(chip8, r)).collect(),
+            "G" => {
+                let mut rest = args;
+                for &target in REGISTERS.iter() {
+                    let size = register_size(target) * 2;
+                    if rest.len() < size {
+                        break;
+                    }
+                    set_register(chip8, target, &rest[..size])?;
+                    rest = &rest[size..];
+                }
+                "OK".to_string()
+            }
+            "p" => register_hex(chip8, register(args)?),
+            "P" => {
+                let (n, value) = args.split_once('=').ok_or(1)?;
+                set_register(chip8, register(n)?, value)?;
+                "OK".to_string()
+            }
+            "m" => {
+                let (addr, len) = address_range(args)?;
+                chip8.memory[addr..addr + len].iter().map(|b| format!("{:02x}", b)).collect()
+            }
+            "M" => {
+                let (range, data) = args.split_once(':').ok_or(1)?;
+                let (addr, len) = address_range(range)?;
+                let bytes = hex_bytes(data)?;
+                if bytes.len() != len {
+                    return Err(1);
+                }
+                chip8.memory[addr..addr + len].copy_from_slice(&bytes);
+                "OK".to_string()
+            }
+            "c" => {
+                resume_at(chip8, args)?;
+                self.waiting = waiting(chip8);
+                self.running = true;
+                return Ok(None);
+            }
+            "s" => {
+                resume_at(chip8, args)?;
+                chip8.cycle();
+                self.waiting = waiting(chip8);
+                format!("S{:02x}", SIGTRAP)
+            }
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next().ok_or(1)?;
+                let addr = fields.next().and_then(|a| usize::from_str_radix(a, 16).ok()).ok_or(1)?;
+                let len = fields.next().and_then(|l| usize::from_str_radix(l, 16).ok()).unwrap_or(1);
+                let insert = command == "Z";
+                match kind {
+                    // software and hardware breakpoints are the same thing here
+                    "0" | "1" => {
+                        if insert {
+                            self.breakpoints.insert(addr);
+                        } else {
+                            self.breakpoints.remove(&addr);
+                        }
+                    }
+                    "2" => {
+                        let (addr, len) = address_range(&format!("{:x},{:x}", addr, len))?;
+                        self.watchpoints.retain(|w| (w.0, w.1) != (addr, len));
+                        if insert {
+                            self.watchpoints.push((addr, len, chip8.memory[addr..addr + len].to_vec()));
+                        }
+                    }
+                    // reads can't be watched
+                    _ => return Ok(Some(String::new())),
+                }
+                "OK".to_string()
+            }
+            "D" => {
+                self.send("OK");
+                self.disconnect();
+                return Ok(None);
+            }
+            "k" => {
+                self.disconnect();
+                return Ok(None);
+            }
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => "PacketSize=4000;qXfer:features:read+".to_string(),
+            "q" if args == "Attached" => "1".to_string(),
+            "q" if args == "C" => "QC1".to_string(),
+            "q" if args == "fThreadInfo" => "m1".to_string(),
+            "q" if args == "sThreadInfo" => "l".to_string(),
+            "q" if args.starts_with("Xfer:features:read:target.xml:") => {
+                let range = &args["Xfer:features:read:target.xml:".len()..];
+                let (offset, len) = range.split_once(',').ok_or(1)?;
+                let offset = usize::from_str_radix(offset, 16).map_err(|_| 1)?;
+                let len = usize::from_str_radix(len, 16).map_err(|_| 1)?;
+                let xml = target_xml();
+                let start = offset.min(xml.len());
+                let end = (start + len).min(xml.len());
+                let more = if end < xml.len() { "m" } else { "l" };
+                format!("{}{}", more, &xml[start..end])
+            }
+            // anything else isn't supported
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+}
+
+// `c` and `s` may give an address to carry on from
+fn resume_at(chip8: &mut Chip8, addr: &str) -> Result<(), u8> {
+    if !addr.is_empty() {
+        let addr = usize::from_str_radix(addr, 16).map_err(|_| 1)?;
+        Target::Pc.set(chip8, addr).map_err(|_| 2)?;
+    }
+    Ok(())
+}
+
+fn register(n: &str) -> Result<Target, u8> {
+    let n = usize::from_str_radix(n, 16).map_err(|_| 1)?;
+    REGISTERS.get(n).copied().ok_or(1)
+}
+
+// little-endian, as GDB expects
+fn register_hex(chip8: &Chip8, target: Target) -> String {
+    let value = target.get(chip8);
+    (0..register_size(target)).map(|i| format!("{:02x}", (value >> (i * 8)) & 0xFF)).collect()
+}
+
+fn set_register(chip8: &mut Chip8, target: Target, hex: &str) -> Result<(), u8> {
+    let value = hex_bytes(hex)?.iter().rev().fold(0, |value, &b| value << 8 | b as usize);
+    target.set(chip8, value).map_err(|_| 2)
+}
+
+fn waiting(chip8: &Chip8) -> bool {
+    chip8.awaiting_keypress || chip8.awaiting_vblank
+}
+
+fn hex_bytes(hex: &str) -> Result<Vec<u8>, u8> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(1);
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().and_then(|s| u8::from_str_radix(s, 16).ok()).ok_or(1))
+        .collect()
+}
+
+// `addr,len` in hex, clipped to the end of memory
+fn address_range(args: &str) -> Result<(usize, usize), u8> {
+    let (addr, len) = args.split_once(',').ok_or(1)?;
+    let addr = usize::from_str_radix(addr, 16).map_err(|_| 1)?;
+    let len = usize::from_str_radix(len, 16).map_err(|_| 1)?;
+    if addr >= 0x1000 {
+        return Err(1);
+    }
+    Ok((addr, len.min(0x1000 - addr)))
+}
@@ -0,0 +1,153 @@
+use crate::emu::Chip8;
+use crate::trace::Follower;
+use std::collections::VecDeque;
+
+// Lets the debugger go backwards. The machine is snapshotted every so often
+// (a clone is everything, RNG included), and in between only the timer ticks
+// and key changes it saw are kept: given those again, `cycle` does exactly
+// what it did the first time, so any earlier point can be rebuilt by running
+// forward from the snapshot before it.
+pub struct History {
+    // oldest first; each is the machine right after its `cycles`th cycle
+    snapshots: VecDeque<Chip8>,
+    // what happened before each cycle, in order
+    inputs: VecDeque<Input>,
+    // cycles, ticks and keys at the last `record`
+    last: Option<(u64, u64, [bool; 16])>,
+}
+
+struct Input {
+    // the cycle it came before (the `cycles` count after that cycle)
+    cycle: u64,
+    ticks: u64,
+    keys: Option<[bool; 16]>,
+}
+
+const SNAPSHOT_INTERVAL: u64 = 1000;
+// about 20 minutes at the default speed, in a few MB
+const MAX_SNAPSHOTS: usize = 600;
+
+impl History {
+    pub fn new() -> History {
+        History {
+            snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    // Called after every cycle. If cycles ran that weren't recorded, the
+    // history starts again from here.
+    pub fn record(&mut self, chip8: &Chip8) {
+        match self.last {
+            Some((cycles, _, _)) if chip8.cycles == cycles => return,
+            Some((cycles, ticks, keys)) if chip8.cycles == cycles + 1 => {
+                if chip8.ticks != ticks || chip8.keys != keys {
+                    self.inputs.push_back(Input {
+                        cycle: chip8.cycles,
+                        ticks: chip8.ticks - ticks,
+                        keys: (chip8.keys != keys).then_some(chip8.keys),
+                    });
+                }
+                if chip8.cycles.is_multiple_of(SNAPSHOT_INTERVAL) {
+                    self.snapshots.push_back(chip8.clone());
+                }
+                if self.snapshots.len() > MAX_SNAPSHOTS {
+                    self.snapshots.pop_front();
+                    let start = self.snapshots[0].cycles;
+                    while self.inputs.front().is_some_and(|i| i.cycle <= start) {
+                        self.inputs.pop_front();
+                    }
+                }
+            }
+            _ => {
+                self.snapshots.clear();
+                self.inputs.clear();
+                self.snapshots.push_back(chip8.clone());
+            }
+        }
+        self.last = Some((chip8.cycles, chip8.ticks, chip8.keys));
+    }
+
+    // The machine was changed by hand while stopped; it is taken as it is now,
+    // as if it had always been that way at this point.
+    pub fn edited(&mut self, chip8: &Chip8) {
+        self.record(chip8);
+        if self.snapshots.back().is_some_and(|s| s.cycles == chip8.cycles) {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_back(chip8.clone());
+        self.last = Some((chip8.cycles, chip8.ticks, chip8.keys));
+    }
+
+    // how far back it goes
+    pub fn oldest(&self) -> Option<&Chip8> {
+        self.snapshots.front()
+    }
+
+    // Runs `chip8` forward to `target` cycles with the recorded inputs,
+    // calling `each` after every cycle.
+    pub fn replay(&self, chip8: &mut Chip8, target: u64, mut each: impl FnMut(&Chip8)) {
+        let mut next = self.inputs.partition_point(|i| i.cycle <= chip8.cycles);
+        while chip8.cycles < target {
+            if let Some(input) = self.inputs.get(next).filter(|i| i.cycle == chip8.cycles + 1) {
+                for _ in 0..input.ticks {
+                    chip8.tick();
+                }
+                if let Some(keys) = input.keys {
+                    chip8.keys = keys;
+                }
+                next += 1;
+            }
+            chip8.cycle();
+            each(chip8);
+        }
+    }
+
+    // The cycle count just before the nth last instruction run, from
+    // `chip8` (which must be where the history ends). Waits for a key or the
+    // display run cycles but no instruction, so they're gone back over.
+    pub fn instructions_back(&self, chip8: &Chip8, n: u64) -> Result<u64, String> {
+        let mut end = chip8.cycles;
+        let mut left = n as usize;
+        if left == 0 {
+            return Ok(end);
+        }
+        // a stretch between snapshots at a time, latest first
+        for snapshot in self.snapshots.iter().rev().filter(|s| s.cycles < chip8.cycles) {
+            let mut machine = snapshot.clone();
+            let mut follower = Follower::new(&machine);
+            let mut ran = Vec::new();
+            self.replay(&mut machine, end, |machine| {
+                if follower.update(machine).is_some() {
+                    ran.push(machine.cycles);
+                }
+            });
+            if ran.len() >= left {
+                return Ok(ran[ran.len() - left] - 1);
+            }
+            left -= ran.len();
+            end = snapshot.cycles;
+        }
+        Err("no more history to go back through".to_string())
+    }
+
+    // The machine as it was after `target` cycles. Everything after that is
+    // forgotten, as running on from there makes a new future.
+    pub fn rewind(&mut self, target: u64) -> Result<Chip8, String> {
+        let snapshot = match self.snapshots.iter().rposition(|s| s.cycles <= target) {
+            Some(n) => n,
+            None => return Err("no more history to go back through".to_string()),
+        };
+        let mut chip8 = self.snapshots[snapshot].clone();
+        self.replay(&mut chip8, target, |_| {});
+        self.snapshots.truncate(snapshot + 1);
+        while self.inputs.back().is_some_and(|i| i.cycle > target) {
+            self.inputs.pop_back();
+        }
+        self.last = Some((chip8.cycles, chip8.ticks, chip8.keys));
+        // all of it needs drawing again
+        chip8.display.mark_dirty();
+        Ok(chip8)
+    }
+}
@@ -0,0 +1,152 @@
+use crate::config::Config;
+use sdl2::keyboard::Keycode;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+// The CHIP-8 hex keypad, in the order its keys appear on the COSMAC VIP:
+// 1 2 3 C
+// 4 5 6 D
+// 7 8 9 E
+// A 0 B F
+const KEYPAD_GRID: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+pub static LAYOUT_NAMES: [&str; 6] = ["qwerty", "azerty", "qwertz", "dvorak", "colemak", "keypad"];
+
+pub struct KeyMap {
+    bindings: HashMap<Keycode, u8>,
+}
+
+impl KeyMap {
+    // the left-hand 4x4 block (1234/QWER/ASDF/ZXCV on a qwerty keyboard)
+    pub fn qwerty() -> KeyMap {
+        KeyMap::builtin("qwerty").unwrap()
+    }
+
+    pub fn builtin(name: &str) -> Option<KeyMap> {
+        use Keycode::*;
+        let grid = match name {
+            "qwerty" => [Num1, Num2, Num3, Num4, Q, W, E, R, A, S, D, F, Z, X, C, V],
+            "azerty" => [Num1, Num2, Num3, Num4, A, Z, E, R, Q, S, D, F, W, X, C, V],
+            "qwertz" => [Num1, Num2, Num3, Num4, Q, W, E, R, A, S, D, F, Y, X, C, V],
+            "dvorak" => [
+                Num1, Num2, Num3, Num4, Quote, Comma, Period, P, A, O, E, U, Semicolon, Q, J, K,
+            ],
+            "colemak" => [Num1, Num2, Num3, Num4, Q, W, F, P, A, R, S, T, Z, X, C, V],
+            // the original numeric keypad bindings
+            "keypad" => [
+                Kp7, Kp8, Kp9, KpDivide, Kp4, Kp5, Kp6, KpMultiply, Kp1, Kp2, Kp3, KpMinus,
+                Kp0, KpPeriod, KpEnter, KpPlus,
+            ],
+            _ => return None,
+        };
+        let mut map = KeyMap {
+            bindings: HashMap::new(),
+        };
+        for (keycode, chip8_key) in grid.iter().zip(KEYPAD_GRID.iter()) {
+            map.bind(*keycode, *chip8_key);
+        }
+        Some(map)
+    }
+
+    // Keymap files start from a built-in layout (qwerty unless `layout` says
+    // otherwise) and rebind individual CHIP-8 keys to one or more host keys:
+    //
+    //   layout = azerty
+    //   5 = Z, Up
+    //   8 = S, Down
+    pub fn from_config(config: &Config) -> Result<KeyMap, String> {
+        let layout = config.get("", "layout").unwrap_or("qwerty");
+        let mut map = KeyMap::builtin(layout)
+            .ok_or_else(|| format!("unknown keyboard layout: {}", layout))?;
+        for entry in config.section("") {
+            if entry.key == "layout" {
+                continue;
+            }
+            let chip8_key = parse_chip8_key(&entry.key)
+                .ok_or_else(|| format!("line {}: not a CHIP-8 key: {}", entry.line, entry.key))?;
+            map.unbind(chip8_key);
+            for name in entry.value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                let keycode = Keycode::from_name(name)
+                    .ok_or_else(|| format!("line {}: unknown key name: {}", entry.line, name))?;
+                map.bind(keycode, chip8_key);
+            }
+        }
+        Ok(map)
+    }
+
+    // either the name of a built-in layout or the path of a keymap file
+    pub fn load(name_or_path: &str) -> Result<KeyMap, String> {
+        if let Some(map) = KeyMap::builtin(name_or_path) {
+            return Ok(map);
+        }
+        let path = Path::new(name_or_path);
+        if !path.exists() {
+            return Err(format!(
+                "{} is neither a keymap file nor a built-in layout ({})",
+                name_or_path,
+                LAYOUT_NAMES.join(", ")
+            ));
+        }
+        let config = Config::load(path)?;
+        KeyMap::from_config(&config).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn bind(&mut self, keycode: Keycode, chip8_key: u8) {
+        self.bindings.insert(keycode, chip8_key);
+    }
+
+    pub fn unbind(&mut self, chip8_key: u8) {
+        self.bindings.retain(|_, k| *k != chip8_key);
+    }
+
+    pub fn get(&self, keycode: Keycode) -> Option<u8> {
+        self.bindings.get(&keycode).copied()
+    }
+}
+
+fn parse_chip8_key(s: &str) -> Option<u8> {
+    let s = s.trim_start_matches("0x");
+    match u8::from_str_radix(s, 16) {
+        Ok(k) if s.len() == 1 && k <= 0xF => Some(k),
+        _ => None,
+    }
+}
+
+// Tracks which bound host keys are held, so that a CHIP-8 key with several
+// host keys stays down until all of them have been released.
+pub struct Keyboard {
+    map: KeyMap,
+    held: HashSet<Keycode>,
+}
+
+impl Keyboard {
+    pub fn new(map: KeyMap) -> Keyboard {
+        Keyboard {
+            map,
+            held: HashSet::new(),
+        }
+    }
+
+    pub fn key_event(&mut self, keycode: Keycode, down: bool) {
+        if self.map.get(keycode).is_none() {
+            return;
+        }
+        if down {
+            self.held.insert(keycode);
+        } else {
+            self.held.remove(&keycode);
+        }
+    }
+
+    pub fn pressed(&self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for keycode in &self.held {
+            if let Some(k) = self.map.get(*keycode) {
+                keys[k as usize] = true;
+            }
+        }
+        keys
+    }
+}
@@ -1,201 +1,659 @@
-#![feature(slice_as_chunks)]
-
+mod accessmap;
+mod cli;
+mod config;
+mod debugger;
+mod disasm;
 mod emu;
+mod expr;
+mod filter;
 mod font;
+mod framebuffer;
+mod gamepad;
+mod gdb;
+mod history;
+mod keymap;
+mod overlay;
+mod palette;
+mod phosphor;
+mod profile;
+mod renderer;
 mod rng;
+mod romdb;
+mod settings;
+mod sha1;
 mod tests;
+mod timing;
+mod trace;
+mod viewer;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
+use std::io::Write;
+use std::sync::mpsc::Receiver;
+
+use crate::cli::{Command, RunOptions};
+use crate::debugger::{Action, Debugger};
+use crate::emu::Chip8;
+use crate::gamepad::{Gamepads, PadMap};
+use crate::gdb::GdbServer;
+use crate::keymap::{KeyMap, Keyboard};
+use crate::palette::{Palette, THEME_NAMES};
+use crate::profile::Profiler;
+use crate::renderer::Renderer;
+use crate::rng::RandomBytes;
+use crate::romdb::RomProfile;
+use crate::settings::{RomSettings, Settings};
+use crate::timing::{Pacer, Timing};
+use crate::trace::Tracer;
+use crate::viewer::{MemoryViewer, SpriteViewer};
 
-use emu::Chip8;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use std::fs::File;
-use std::io::Read;
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Run `chip8 --help` for usage.");
+            std::process::exit(2);
+        }
+    };
+    let result = match command {
+        Command::Run(options) => run(options),
+        Command::Headless(options, frames) => headless(options, frames),
+        Command::Debug(options) => debug(options),
+        Command::Info(rom) => info(&rom),
+        Command::Disasm(rom, access_map) => disasm(&rom, access_map.as_deref()),
+        Command::TraceDiff(a, b, context) => tracediff(&a, &b, context),
+        Command::Help => {
+            print!("{}", cli::USAGE);
+            println!();
+            println!("{}", cli::names_help());
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
 
-use crate::emu::Chip8Options;
+fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    if data.len() > 0x1000 - 0x200 {
+        return Err(format!(
+            "{}: ROM is {} bytes, more than the {} that fit in memory",
+            path,
+            data.len(),
+            0x1000 - 0x200
+        ));
+    }
+    Ok(data)
+}
 
-fn main() {
-    /*let mut chip8rom = [0u8; 0x1000 - 0x200];
+struct Rom {
+    data: Vec<u8>,
+    sha1: String,
+    profile: Option<&'static RomProfile>,
+}
 
-    // ld v0, $12
-    chip8rom[0] = 0x60;
-    chip8rom[1] = 0x12;
+fn load_rom(path: &str) -> Result<Rom, String> {
+    let data = read_rom(path)?;
+    let sha1 = sha1::sha1_hex(&data);
+    let profile = romdb::lookup(&sha1);
+    Ok(Rom {
+        data,
+        sha1,
+        profile,
+    })
+}
 
-    // ld v1, $56
-    chip8rom[2] = 0x61;
-    chip8rom[3] = 0x56;
+fn new_chip8(rom: &Rom, options: &RunOptions) -> Chip8 {
+    let mut chip8rom = [0u8; 0x1000 - 0x200];
+    chip8rom[..rom.data.len()].copy_from_slice(&rom.data);
+    let mut chip8 = Chip8::new(chip8rom, options.chip8_options(rom.profile));
+    if let Some(seed) = options.seed {
+        chip8.rng = RandomBytes::with_seed(seed);
+    }
+    if options.access_map.is_some() {
+        chip8.record_access();
+    }
+    chip8
+}
 
-    let mut chip8 = emu::Chip8::new(chip8rom);
-    chip8.cycle();
-    chip8.cycle();*/
-    let usage = "chip8 <file> [speed multiplier] <options>";
+fn info(path: &str) -> Result<(), String> {
+    let rom = load_rom(path)?;
+    println!("File: {}", path);
+    println!(
+        "Size: {} bytes ({:#05x}-{:#05x})",
+        rom.data.len(),
+        0x200,
+        0x200 + rom.data.len()
+    );
+    println!("SHA-1: {}", rom.sha1);
+    match rom.profile {
+        Some(profile) => {
+            println!("Title: {}", profile.title);
+            if !profile.authors.is_empty() {
+                println!("Authors: {}", profile.authors);
+            }
+            println!("Platform: {}", profile.platform);
+            let options = profile.options();
+            println!("Quirks: {}", options.quirks_description());
+            if let Some(tickrate) = profile.tickrate {
+                println!("Speed: {} instructions per frame", tickrate);
+            }
+            if !profile.keys.is_empty() {
+                println!("Keys: {}", profile.keys_description());
+            }
+        }
+        None => println!("Not in the ROM database"),
+    }
+    Ok(())
+}
 
-    let arguments_length = std::env::args().len();
-    if arguments_length < 2 {
-        println!("{}", usage);
-        println!("Possible options: savingIncreasesRegI, shiftVyNotVx");
-        return;
+fn disasm(path: &str, access_map: Option<&str>) -> Result<(), String> {
+    let data = read_rom(path)?;
+    let mut memory = vec![0u8; 0x200];
+    memory.extend_from_slice(&data);
+    let lines = match access_map {
+        Some(map_path) => {
+            let text = std::fs::read_to_string(map_path).map_err(|e| format!("{}: {}", map_path, e))?;
+            let access = accessmap::parse(&text).map_err(|e| format!("{}: {}", map_path, e))?;
+            disasm::disassemble_mapped(&memory, &access, 0x200, memory.len())
+        }
+        None => disasm::disassemble_range(&memory, 0x200, memory.len()),
+    };
+    for line in lines {
+        println!("{}", line);
     }
-    
-    let speed_multiplier: f32 = match str::parse(
-        &std::env::args().nth(2).unwrap_or("1".to_string())[..]
-    ) {
-        Ok(x) => x,
-        Err(_) => {
-            println!("Incorrect speed multiplier");
-            println!("Usage: {}", usage);
+    Ok(())
+}
+
+// exits with 1 if the traces differ, like diff
+fn tracediff(a: &str, b: &str, context: usize) -> Result<(), String> {
+    let read = |path: &str| std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e));
+    match trace::diff(&read(a)?, &read(b)?, context)? {
+        Some(report) => {
+            print!("{}", report);
             std::process::exit(1);
         }
-    };
-    let default_cycles_per_frame = 8f32;
-    let cycles_per_frame: usize = (default_cycles_per_frame * speed_multiplier) as usize;
-    let min_cycles = 1f32;
-    let max_cycles = 1000f32;
-    let min_multiplier = min_cycles / default_cycles_per_frame;
-    let max_multiplier = max_cycles / default_cycles_per_frame;
-    if cycles_per_frame < min_cycles as usize {
-        println!("Speed multiplier too low (min: {})", min_multiplier);
-        println!("{}", usage);
-        return;
-    } else if cycles_per_frame > max_cycles as usize {
-        println!("Speed multiplier too high (max: {})", max_multiplier);
-        println!("{}", usage);
-        return;
-    }
-
-    let mut saving_increases_reg_i= false;
-
-    let mut shift_vy_not_vx = false;
-
-    for i in 3..=4 {
-        let option = std::env::args().nth(i).unwrap_or("none".to_string());
-        if option == "savingIncreasesRegI" {
-            saving_increases_reg_i = true;
-        } else if option == "shiftVyNotVx" {
-            shift_vy_not_vx = true;
-        } else if option == "none" {
-            
+        None => {
+            println!("The traces match");
+            Ok(())
+        }
+    }
+}
+
+// runs the ROM for a number of frames without any window or input, then prints the screen
+fn headless(options: RunOptions, frames: u64) -> Result<(), String> {
+    let rom = load_rom(&options.rom)?;
+    let mut chip8 = new_chip8(&rom, &options);
+    let mut pacer = Pacer::new(options.ips(rom.profile), options.timing);
+    let mut probes = Probes::new(&options, &chip8, rom.data.len())?;
+    for _ in 0..frames {
+        if probes.is_empty() {
+            pacer.run_frame(&mut chip8);
         } else {
-            println!("Unknown option: {}", option);
-            std::process::exit(1);
+            pacer.run_frame_until(&mut chip8, |c| {
+                probes.log(c);
+                false
+            });
         }
     }
+    probes.finish(&chip8)?;
+    let width = chip8.display.width();
+    let mut line = String::new();
+    for (x, _, pixel) in chip8.display.iter() {
+        line.push(if pixel != 0 { '#' } else { '.' });
+        if x == width - 1 {
+            println!("{}", line);
+            line.clear();
+        }
+    }
+    Ok(())
+}
 
-    let file_name = std::env::args().nth(1).expect("No file specified");
+fn run(mut options: RunOptions) -> Result<(), String> {
+    let rom_name = std::path::Path::new(&options.rom)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let rom = load_rom(&options.rom)?;
+    let profile = rom.profile;
+    if let Some(profile) = profile {
+        println!("{} ({})", profile.title, profile.platform);
+        if !profile.keys.is_empty() {
+            println!("Keys: {}", profile.keys_description());
+        }
+    }
 
-    let mut chip8rom = [0u8; 0x1000 - 0x200];
-    let mut the_file = File::open(file_name).expect("Error opening file");
-    the_file
-        .read(&mut chip8rom)
-        .expect("Error reading from file");
-
-    let chip8options = Chip8Options {
-        saving_increases_reg_i: saving_increases_reg_i,
-        shift_vy_not_vx: shift_vy_not_vx
+    let mut settings = Settings::load().map_err(|e| format!("loading settings: {}", e))?;
+    let mut saved = settings.rom(&rom.sha1);
+    options.fill_in(&saved, profile, &settings.defaults());
+
+    let mut chip8 = new_chip8(&rom, &options);
+    let mut probes = Probes::new(&options, &chip8, rom.data.len())?;
+    let mut pacer = Pacer::new(options.ips(profile), options.timing);
+    let mut palette_name = options.palette.clone();
+    let palette = match &options.palette {
+        Some(p) => Palette::parse(p).map_err(|e| format!("palette {}: {}", p, e))?,
+        None => Palette::default(),
     };
+    let mut renderer = Renderer::new(palette, options.filter, options.persistence);
 
-    let mut chip8 = emu::Chip8::new(chip8rom, chip8options);
-
-    const CHIP8_WIDTH: usize = 64;
-    const CHIP8_HEIGHT: usize = 32;
+    let mut layout_name = options.keymap.clone();
+    let keymap = load_keymap(options.keymap).map_err(|e| format!("loading keymap: {}", e))?;
+    let mut keyboard = Keyboard::new(keymap);
 
-    const SCR_MULTIPLIER: usize = 12;
-    const X_MULT: usize = SCR_MULTIPLIER * 4;
-    const Y_MULT: usize = SCR_MULTIPLIER;
-
-    const WIDTH: usize = CHIP8_WIDTH * SCR_MULTIPLIER;
-    const HEIGHT: usize = CHIP8_HEIGHT * SCR_MULTIPLIER;
+    let hints = profile.map_or(&[][..], |p| p.keys);
+    let padmap = PadMap::load(&rom_name, hints)
+        .map_err(|e| format!("loading gamepad mappings: {}", e))?;
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
+    let mut gamepads = match sdl_context.game_controller() {
+        Ok(subsystem) => Some(Gamepads::new(subsystem, padmap)),
+        Err(e) => {
+            eprintln!("Controller support unavailable: {}", e);
+            None
+        }
+    };
 
-    let window = video_subsystem
-        .window("chip8", WIDTH as u32, HEIGHT as u32)
+    let screen_width = chip8.display.width() as u32;
+    let screen_height = chip8.display.height() as u32;
+    let mut window = video_subsystem
+        .window("chip8", screen_width * options.scale, screen_height * options.scale)
         .position_centered()
+        .resizable()
         .build()
         .unwrap();
+    window.set_minimum_size(screen_width, screen_height).unwrap();
 
     let mut canvas = window
         .into_canvas()
         .present_vsync()
         .build()
         .unwrap();
+    // SDL keeps the screen's aspect ratio when the window is resized, with black
+    // bars around it, and scales with nearest neighbour
+    canvas.set_logical_size(screen_width, screen_height).unwrap();
+    canvas.set_integer_scale(options.integer_scaling).unwrap();
 
     let texture_creator = canvas.texture_creator();
-    let mut texture = texture_creator
-        .create_texture_streaming(
-            sdl2::pixels::PixelFormatEnum::ARGB8888,
-            WIDTH as u32,
-            HEIGHT as u32,
-        )
+    let mut texture: Option<Texture> = None;
+    // F2's debug overlay, drawn over the screen
+    let mut overlay_texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, overlay::WIDTH as u32, overlay::HEIGHT as u32)
         .unwrap();
+    overlay_texture.set_blend_mode(BlendMode::Blend);
+    let mut show_overlay = false;
+    // F10's memory viewer and F12's sprite viewer, in windows of their own,
+    // made hidden up front so each has one texture for as long as it's open
+    let memory_canvas = view_canvas(&video_subsystem, "chip8 memory", viewer::MEMORY_WIDTH, viewer::MEMORY_HEIGHT);
+    let memory_creator = memory_canvas.as_ref().map(|canvas| canvas.texture_creator());
+    let mut memory_view = memory_canvas
+        .zip(memory_creator.as_ref())
+        .map(|(canvas, creator)| View::new(canvas, creator, viewer::MEMORY_WIDTH, viewer::MEMORY_HEIGHT));
+    let sprite_canvas = view_canvas(&video_subsystem, "chip8 sprites", viewer::SPRITES_WIDTH, viewer::SPRITES_HEIGHT);
+    let sprite_creator = sprite_canvas.as_ref().map(|canvas| canvas.texture_creator());
+    let mut sprite_view = sprite_canvas
+        .zip(sprite_creator.as_ref())
+        .map(|(canvas, creator)| View::new(canvas, creator, viewer::SPRITES_WIDTH, viewer::SPRITES_HEIGHT));
     let mut event_pump = sdl_context.event_pump().unwrap();
     
     //let mut frames = 0;
     //let mut start = std::time::Instant::now();
     //let mut last_printed = 0;
 
+    let mut last_frame = std::time::Instant::now();
+
+    // with --debug, commands come from the terminal while the window runs,
+    // starting paused so breakpoints can be set first
+    let mut debugger = None;
+    let mut commands = None;
+    // the frame a `continue <frames>` stops at
+    let mut stop_at_frame = None;
+    let mut paused = false;
+    if options.debug {
+        debugger = Some(Debugger::new());
+        commands = Some(read_commands());
+        paused = true;
+        println!("Type help for debugger commands");
+        println!("{}", debugger::location(&chip8));
+        prompt();
+        set_title(&mut canvas, &chip8, paused);
+    }
+    // with --gdb, GDB takes over pausing and stepping while it's connected
+    let mut gdb = match options.gdb {
+        Some(port) => Some(listen(port)?),
+        None => None,
+    };
+
     'running: loop {
         //frames += 1;
-        chip8.tick();
-        for _ in 0..cycles_per_frame {
-            chip8.cycle();
+        let now = std::time::Instant::now();
+        if !paused {
+            let stopped = if debugger.is_none() && gdb.is_none() && probes.is_empty() {
+                pacer.advance(&mut chip8, now - last_frame);
+                false
+            } else {
+                pacer.advance_until(&mut chip8, now - last_frame, |c| {
+                    probes.log(c);
+                    match (debugger.as_mut(), gdb.as_mut()) {
+                        (Some(debugger), _) => debugger.check(c),
+                        (_, Some(gdb)) => gdb.check(c),
+                        _ => false,
+                    }
+                })
+            };
+            if stopped || stop_at_frame.is_some_and(|frame| pacer.frames() >= frame) {
+                paused = true;
+                stop_at_frame = None;
+                if debugger.is_some() {
+                    println!("{}", debugger::location(&chip8));
+                    prompt();
+                }
+                set_title(&mut canvas, &chip8, paused);
+            }
+        }
+        last_frame = now;
+
+        if let Some(gdb) = gdb.as_mut() {
+            let was_connected = gdb.connected();
+            gdb.poll(&mut chip8);
+            let was_paused = paused;
+            if gdb.connected() {
+                paused = !gdb.running();
+            } else if was_connected {
+                paused = false;
+            }
+            if paused != was_paused {
+                set_title(&mut canvas, &chip8, paused);
+            }
         }
 
-        const ROWSIZE: usize = WIDTH * 4;
-        texture
-            .with_lock(None, |pixelarray, _| -> () {
-                let (chunks, _remainder) = pixelarray.as_chunks_mut::<ROWSIZE>();
-                for src_y in 0..CHIP8_HEIGHT {
-                    for src_x in 0..CHIP8_WIDTH {
-                        let pixel_value = chip8.display[src_y][src_x];
-                        let to_set = if pixel_value == 1 { 255 } else { 0 };
-                        let dest_y_start = src_y * Y_MULT;
-                        let dest_x_start = src_x * X_MULT;
-                        let dest_x_end = dest_x_start + X_MULT;
-                        let dest_y_end = dest_y_start + Y_MULT;
-
-                        for dest_y in dest_y_start..dest_y_end {
-                            for dest_x in dest_x_start..dest_x_end {
-                                chunks[dest_y][dest_x] = to_set;
-                            }
-                        }
+        if let (Some(debugger), Some(commands)) = (debugger.as_mut(), commands.as_ref()) {
+            for line in commands.try_iter() {
+                match debugger.command(&mut chip8, &line) {
+                    Ok(Action::Continue(frames)) => {
+                        paused = false;
+                        stop_at_frame = frames.map(|n| pacer.frames() + n);
                     }
+                    Ok(Action::Stepped) => {
+                        paused = true;
+                        println!("{}", debugger::location(&chip8));
+                    }
+                    Ok(Action::Quit) => break 'running,
+                    Ok(Action::None) => {}
+                    Err(e) => println!("error: {}", e),
                 }
-            })
-            .unwrap();
+                prompt();
+                set_title(&mut canvas, &chip8, paused);
+            }
+        }
+
+        if renderer.update(&chip8.display) {
+            let (width, height) = renderer.frame_size();
+            // (re)created at the size of the renderer's frames, which SDL then stretches
+            let query = texture.as_ref().map(Texture::query);
+            if query.map(|q| (q.width, q.height)) != Some((width as u32, height as u32)) {
+                texture = Some(
+                    texture_creator
+                        .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+                        .unwrap(),
+                );
+            }
+            let texture = texture.as_mut().unwrap();
+            texture.update(None, &renderer.render(width, height), width * 4).unwrap();
+        }
 
-        canvas.copy(&texture, None, None).unwrap();
+        canvas.set_draw_color(sdl2::pixels::Color::BLACK);
+        canvas.clear();
+        if let Some(texture) = &texture {
+            canvas.copy(texture, None, None).unwrap();
+        }
+        if show_overlay {
+            overlay_texture.update(None, &overlay::render(&chip8), overlay::WIDTH * 4).unwrap();
+            canvas.copy(&overlay_texture, None, None).unwrap();
+        }
 
         for event in event_pump.poll_iter() {
             match event {
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    // with more than one window open, closing the main one doesn't quit by itself
+                    if let Some(view) = memory_view.as_mut().filter(|v| v.shown_id() == Some(window_id)) {
+                        view.close();
+                    } else if let Some(view) = sprite_view.as_mut().filter(|v| v.shown_id() == Some(window_id)) {
+                        view.close();
+                    } else {
+                        break 'running;
+                    }
+                }
+                Event::KeyDown {
+                    window_id,
+                    keycode: Some(key),
+                    ..
+                } if shown_id(&memory_view) == Some(window_id) => {
+                    if let Some(view) = memory_view.as_mut() {
+                        if !view.viewer.as_mut().is_some_and(|viewer| memory_key(viewer, key, &chip8)) {
+                            view.close();
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    window_id,
+                    keycode: Some(key),
+                    ..
+                } if shown_id(&sprite_view) == Some(window_id) => {
+                    if let Some(view) = sprite_view.as_mut() {
+                        if !view.viewer.as_mut().is_some_and(|viewer| sprite_key(viewer, key, &chip8)) {
+                            view.close();
+                        }
+                    }
+                }
+                Event::MouseWheel { window_id, y, .. } if shown_id(&memory_view) == Some(window_id) => {
+                    if let Some(viewer) = memory_view.as_mut().and_then(|v| v.viewer.as_mut()) {
+                        viewer.scroll(-y as isize * 3);
+                    }
+                }
+                Event::MouseWheel { window_id, y, .. } if shown_id(&sprite_view) == Some(window_id) => {
+                    if let Some(viewer) = sprite_view.as_mut().and_then(|v| v.viewer.as_mut()) {
+                        let line = viewer.line_len() as isize;
+                        viewer.move_by(-y as isize * line);
+                    }
+                }
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
-                /*Event::KeyDown { keycode: Some(Keycode::J), .. } => {
-                    chip8.tick();
-                    chip8.cycle();
-                },
-                Event::KeyDown { keycode: Some(Keycode::K), .. } => {
-                    chip8.tick();
-                    for _ in 0..100 {
-                        chip8.cycle();
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => show_overlay = !show_overlay,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } => {
+                    if let Some(view) = memory_view.as_mut() {
+                        view.toggle(|| MemoryViewer::new(&chip8));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => {
+                    if let Some(view) = sprite_view.as_mut() {
+                        view.toggle(SpriteViewer::new);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::F3 | Keycode::F4)),
+                    ..
+                } => {
+                    if pacer.timing() == Timing::Vip {
+                        println!("Speed is fixed with VIP timing");
+                        continue;
                     }
-                },*/
+                    let factor = if key == Keycode::F3 { 0.8 } else { 1.25 };
+                    let ips = (pacer.ips() * factor).clamp(cli::MIN_IPS, cli::MAX_IPS);
+                    pacer.set_ips(ips);
+                    println!(
+                        "Speed: {:.2}x ({:.0} instructions/s)",
+                        ips / cli::DEFAULT_IPS,
+                        ips
+                    );
+                    saved.ips = Some(ips);
+                    save_settings(&mut settings, &rom.sha1, &options.rom, &saved);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => {
+                    let next = match THEME_NAMES
+                        .iter()
+                        .position(|&name| Some(name) == palette_name.as_deref())
+                    {
+                        Some(i) => THEME_NAMES[(i + 1) % THEME_NAMES.len()],
+                        None => THEME_NAMES[0],
+                    };
+                    println!("Colour theme: {}", next);
+                    renderer.set_palette(Palette::builtin(next).unwrap());
+                    palette_name = Some(next.to_string());
+                    saved.palette = Some(next.to_string());
+                    save_settings(&mut settings, &rom.sha1, &options.rom, &saved);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    let next = match keymap::LAYOUT_NAMES
+                        .iter()
+                        .position(|&name| Some(name) == layout_name.as_deref())
+                    {
+                        Some(i) => keymap::LAYOUT_NAMES[(i + 1) % keymap::LAYOUT_NAMES.len()],
+                        None => keymap::LAYOUT_NAMES[0],
+                    };
+                    println!("Keyboard layout: {}", next);
+                    keyboard = Keyboard::new(KeyMap::builtin(next).unwrap());
+                    update_keys(&mut chip8, &keyboard, &gamepads);
+                    layout_name = Some(next.to_string());
+                    saved.keymap = Some(next.to_string());
+                    save_settings(&mut settings, &rom.sha1, &options.rom, &saved);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => {
+                    let window = canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off,
+                    };
+                    if let Err(e) = window.set_fullscreen(fullscreen) {
+                        eprintln!("Error switching fullscreen: {}", e);
+                    }
+                }
+                // while GDB is connected these only stop the machine, for GDB to take over
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5 | Keycode::F6 | Keycode::F7),
+                    ..
+                } if gdb.as_ref().is_some_and(GdbServer::connected) => {
+                    if let Some(gdb) = gdb.as_mut() {
+                        gdb.interrupt();
+                    }
+                    paused = true;
+                    set_title(&mut canvas, &chip8, paused);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    paused = !paused;
+                    if paused && debugger.is_some() {
+                        println!("{}", debugger::location(&chip8));
+                        prompt();
+                    }
+                    set_title(&mut canvas, &chip8, paused);
+                }
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::F6 | Keycode::F7)),
+                    keymod,
+                    ..
+                } => {
+                    // stepping pauses first, if need be
+                    paused = true;
+                    // recorded, so the debugger can go back over them
+                    let mut record = |chip8: &Chip8| {
+                        probes.log(chip8);
+                        if let Some(debugger) = debugger.as_mut() {
+                            debugger.record(chip8);
+                        }
+                    };
+                    if key == Keycode::F7 {
+                        pacer.run_frame_until(&mut chip8, |c| {
+                            record(c);
+                            false
+                        });
+                    } else {
+                        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        for _ in 0..if shift { 100 } else { 1 } {
+                            chip8.cycle();
+                            record(&chip8);
+                        }
+                    }
+                    set_title(&mut canvas, &chip8, paused);
+                }
                 Event::KeyDown {
                     keycode: Some(the_key),
                     ..
-                } => try_handle_chip8_keycode(the_key, &mut chip8, true),
+                } => {
+                    keyboard.key_event(the_key, true);
+                    update_keys(&mut chip8, &keyboard, &gamepads);
+                }
                 Event::KeyUp {
                     keycode: Some(the_key),
                     ..
-                } => try_handle_chip8_keycode(the_key, &mut chip8, false),
-                _ => {}
+                } => {
+                    keyboard.key_event(the_key, false);
+                    update_keys(&mut chip8, &keyboard, &gamepads);
+                }
+                other => {
+                    if let Some(pads) = gamepads.as_mut() {
+                        pads.handle_event(&other);
+                        update_keys(&mut chip8, &keyboard, &gamepads);
+                    }
+                }
             }
         }
         canvas.present();
 
+        if let Some(view) = memory_view.as_mut() {
+            let shown = view.viewer.as_mut().map(|viewer| {
+                viewer.update(&chip8);
+                viewer.render(&chip8)
+            });
+            if let Some(rgba) = shown {
+                view.present(&rgba);
+            }
+        }
+        if let Some(view) = sprite_view.as_mut() {
+            if let Some((title, rgba)) = view.viewer.as_ref().map(|viewer| (viewer.title(), viewer.render(&chip8))) {
+                if view.canvas.window().title() != title {
+                    view.canvas.window_mut().set_title(&title).unwrap();
+                }
+                view.present(&rgba);
+            }
+        }
+
         /*if start.elapsed() > std::time::Duration::new(1, 0) {
             let dt = start.elapsed().as_secs_f64();
             let fc = frames - last_printed;
@@ -204,62 +662,304 @@ fn main() {
             println!("\nfps: {:.1}\n", fc as f64/dt);
         }*/
     }
-    /*let file_name = std::env::args().nth(1).expect("No file specified (chip8 file startpos)");
-    let start_pos: usize = str::parse(
-        &std::env::args().nth(2).unwrap_or("512".to_string())[..]
-    ).expect("Incorrect start position (chip8 file startpos)");
+    probes.finish(&chip8)
+}
 
+// debugs without a window, taking commands from the terminal (or GDB)
+fn debug(options: RunOptions) -> Result<(), String> {
+    let rom = load_rom(&options.rom)?;
+    let mut chip8 = new_chip8(&rom, &options);
+    let mut pacer = Pacer::new(options.ips(rom.profile), options.timing);
+    let mut probes = Probes::new(&options, &chip8, rom.data.len())?;
+    if let Some(port) = options.gdb {
+        serve_gdb(listen(port)?, &mut chip8, &mut pacer, &mut probes);
+        return probes.finish(&chip8);
+    }
+    let mut debugger = Debugger::new();
+    println!("Type help for commands");
+    println!("{}", debugger::location(&chip8));
+    loop {
+        prompt();
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            break;
+        }
+        match debugger.command(&mut chip8, &line) {
+            Ok(Action::Continue(frames)) => {
+                let start = pacer.frames();
+                let mut check = |c: &Chip8| {
+                    probes.log(c);
+                    debugger.check(c)
+                };
+                while !pacer.run_frame_until(&mut chip8, &mut check) {
+                    if frames.is_some_and(|n| pacer.frames() - start >= n) {
+                        break;
+                    }
+                }
+                println!("{}", debugger::location(&chip8));
+            }
+            Ok(Action::Stepped) => println!("{}", debugger::location(&chip8)),
+            Ok(Action::Quit) => break,
+            Ok(Action::None) => {}
+            Err(e) => println!("error: {}", e),
+        }
+    }
+    probes.finish(&chip8)
+}
 
-    let mut file_binary = [0u8; 0x1000 - 0x200];
-    let mut the_file = File::open(file_name).expect("Error opening file");
-    the_file.read(&mut file_binary).expect("Error reading from file");
+fn listen(port: u16) -> Result<GdbServer, String> {
+    let gdb = GdbServer::new(port).map_err(|e| format!("Error starting the GDB server: {}", e))?;
+    println!("Waiting for GDB on 127.0.0.1:{}", gdb.port());
+    Ok(gdb)
+}
 
-    let mut chip8 = emu::Chip8::new(start_pos, file_binary);*/
-    /*
+// runs the machine for GDB in real time, until it disconnects
+fn serve_gdb(mut gdb: GdbServer, chip8: &mut Chip8, pacer: &mut Pacer, probes: &mut Probes) {
+    while !gdb.connected() {
+        gdb.poll(chip8);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let mut last_frame = std::time::Instant::now();
+    while gdb.connected() {
+        let now = std::time::Instant::now();
+        if gdb.running() {
+            pacer.advance_until(chip8, now - last_frame, |c| {
+                probes.log(c);
+                gdb.check(c)
+            });
+        }
+        last_frame = now;
+        gdb.poll(chip8);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
 
+// what watches every instruction run, as asked for on the command line
+struct Probes {
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    profile: Option<String>,
+    heatmap: Option<String>,
+    // kept by the machine itself
+    access_map: Option<String>,
+}
 
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+impl Probes {
+    fn new(options: &RunOptions, chip8: &Chip8, rom_len: usize) -> Result<Probes, String> {
+        let tracer = match &options.trace {
+            Some(path) => Some(Tracer::new(path, options.trace_pc.clone(), options.trace_cycles.clone(), chip8)?),
+            None => None,
+        };
+        let profiling = options.profile.is_some() || options.heatmap.is_some();
+        Ok(Probes {
+            tracer,
+            profiler: profiling.then(|| Profiler::new(chip8, rom_len)),
+            profile: options.profile.clone(),
+            heatmap: options.heatmap.clone(),
+            access_map: options.access_map.clone(),
+        })
+    }
 
-    let window = video_subsystem.window(
-        "chip8",
-        800,
-        600
-    )
-        .position_centered()
-        .build()
-        .unwrap();
+    fn is_empty(&self) -> bool {
+        self.tracer.is_none() && self.profiler.is_none()
+    }
 
-    let mut canvas = window.into_canvas().build().unwrap();
+    fn log(&mut self, chip8: &Chip8) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.log(chip8);
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.log(chip8);
+        }
+    }
 
-    canvas.set_draw_color(Color::RGB(0, 255, 255));
-    canvas.clear();
-    canvas.present();
+    // writes the profile and access map, if any
+    fn finish(self, chip8: &Chip8) -> Result<(), String> {
+        if let (Some(path), Some(access)) = (&self.access_map, &chip8.access) {
+            std::fs::write(path, accessmap::export(access)).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(profiler) = &self.profiler {
+            if let Some(path) = &self.profile {
+                profiler.write_report(path)?;
+            }
+            if let Some(path) = &self.heatmap {
+                profiler.write_heatmap(path)?;
+            }
+        }
+        Ok(())
+    }
+}
 
-    loop {
-        canvas.present();
-        std::thread::sleep(std::time::Duration::from_millis(16));
-    }*/
+fn prompt() {
+    print!("(chip8) ");
+    std::io::stdout().flush().unwrap();
+}
+
+// lines typed into the terminal, read on another thread so the window keeps running
+fn read_commands() -> Receiver<String> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// A viewer's window, hidden while the viewer is closed
+struct View<'a, T> {
+    canvas: Canvas<Window>,
+    texture: Texture<'a>,
+    width: usize,
+    // Some while it's open
+    viewer: Option<T>,
 }
 
-fn try_handle_chip8_keycode(keycode: Keycode, chip8: &mut Chip8, down: bool) {
-    match keycode {
-        Keycode::KpPeriod => chip8.keys[0x0] = down,
-        Keycode::Kp7 => chip8.keys[0x1] = down,
-        Keycode::Kp8 => chip8.keys[0x2] = down,
-        Keycode::Kp9 => chip8.keys[0x3] = down,
-        Keycode::Kp4 => chip8.keys[0x4] = down,
-        Keycode::Kp5 => chip8.keys[0x5] = down,
-        Keycode::Kp6 => chip8.keys[0x6] = down,
-        Keycode::Kp1 => chip8.keys[0x7] = down,
-        Keycode::Kp2 => chip8.keys[0x8] = down,
-        Keycode::Kp3 => chip8.keys[0x9] = down,
-        Keycode::Kp0 => chip8.keys[0xA] = down,
-        Keycode::KpEnter => chip8.keys[0xB] = down,
-        Keycode::KpDivide => chip8.keys[0xC] = down,
-        Keycode::KpMultiply => chip8.keys[0xD] = down,
-        Keycode::KpMinus => chip8.keys[0xE] = down,
-        Keycode::KpPlus => chip8.keys[0xF] = down,
+impl<'a, T> View<'a, T> {
+    fn new(
+        canvas: Canvas<Window>,
+        creator: &'a TextureCreator<WindowContext>,
+        width: usize,
+        height: usize,
+    ) -> View<'a, T> {
+        let texture = creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+            .unwrap();
+        View {
+            canvas,
+            texture,
+            width,
+            viewer: None,
+        }
+    }
+
+    // the window's id while it's open, to tell which events are its
+    fn shown_id(&self) -> Option<u32> {
+        self.viewer.as_ref().map(|_| self.canvas.window().id())
+    }
+
+    fn toggle(&mut self, open: impl FnOnce() -> T) {
+        if self.viewer.is_some() {
+            self.close();
+        } else {
+            self.viewer = Some(open());
+            self.canvas.window_mut().show();
+        }
+    }
+
+    fn close(&mut self) {
+        self.viewer = None;
+        self.canvas.window_mut().hide();
+    }
+
+    fn present(&mut self, rgba: &[u8]) {
+        self.texture.update(None, rgba, self.width * 4).unwrap();
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
+
+fn shown_id<T>(view: &Option<View<T>>) -> Option<u32> {
+    view.as_ref().and_then(View::shown_id)
+}
+
+// a hidden window for a viewer, scaled up from its image
+fn view_canvas(video: &sdl2::VideoSubsystem, title: &str, width: usize, height: usize) -> Option<Canvas<Window>> {
+    let opened = (|| -> Result<Canvas<Window>, String> {
+        let window = video
+            .window(title, width as u32 * 3, height as u32 * 3)
+            .resizable()
+            .hidden()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        canvas.set_logical_size(width as u32, height as u32).map_err(|e| e.to_string())?;
+        Ok(canvas)
+    })();
+    match opened {
+        Ok(canvas) => Some(canvas),
+        Err(e) => {
+            eprintln!("Error opening {}: {}", title, e);
+            None
+        }
+    }
+}
+
+// Keys in the memory viewer's window; false if it's to close.
+fn memory_key(viewer: &mut MemoryViewer, key: Keycode, chip8: &Chip8) -> bool {
+    let page = viewer::MEMORY_ROWS as isize;
+    match key {
+        Keycode::Escape => return false,
+        Keycode::Up => viewer.scroll(-1),
+        Keycode::Down => viewer.scroll(1),
+        Keycode::PageUp => viewer.scroll(-page),
+        Keycode::PageDown => viewer.scroll(page),
+        Keycode::Home => viewer.show(0),
+        Keycode::End => viewer.show(0xFFF),
+        Keycode::I => viewer.show(chip8.reg_i),
+        Keycode::P => viewer.show(chip8.reg_pc),
+        _ => {}
+    }
+    true
+}
+
+// Keys in the sprite viewer's window; false if it's to close.
+fn sprite_key(viewer: &mut SpriteViewer, key: Keycode, chip8: &Chip8) -> bool {
+    let (line, page) = (viewer.line_len() as isize, viewer.page_len() as isize);
+    match key {
+        Keycode::Escape => return false,
+        Keycode::Left => viewer.move_by(-1),
+        Keycode::Right => viewer.move_by(1),
+        Keycode::Up => viewer.move_by(-line),
+        Keycode::Down => viewer.move_by(line),
+        Keycode::PageUp => viewer.move_by(-page),
+        Keycode::PageDown => viewer.move_by(page),
+        Keycode::Minus | Keycode::KpMinus => viewer.resize(-1),
+        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => viewer.resize(1),
+        Keycode::Home => viewer.show(0),
+        Keycode::I => viewer.show(chip8.reg_i),
         _ => {}
     }
+    true
+}
+
+// shows where the machine is stopped while paused
+fn set_title(canvas: &mut Canvas<Window>, chip8: &Chip8, paused: bool) {
+    let title = if !paused {
+        "chip8".to_string()
+    } else {
+        format!("chip8 - paused at {}", debugger::location(chip8))
+    };
+    canvas.window_mut().set_title(&title).unwrap();
+}
+
+fn save_settings(settings: &mut Settings, sha1: &str, rom_path: &str, saved: &RomSettings) {
+    let rom_file = std::path::Path::new(rom_path)
+        .file_name()
+        .map_or(rom_path.into(), |name| name.to_string_lossy());
+    if let Err(e) = settings.set_rom(sha1, &rom_file, saved) {
+        eprintln!("Error saving settings: {}", e);
+    }
+}
+
+fn update_keys(chip8: &mut Chip8, keyboard: &Keyboard, gamepads: &Option<Gamepads>) {
+    let from_keyboard = keyboard.pressed();
+    let from_gamepads = gamepads.as_ref().map_or([false; 16], |pads| pads.pressed());
+    for i in 0..16 {
+        chip8.keys[i] = from_keyboard[i] || from_gamepads[i];
+    }
+}
+
+// an explicit layout or keymap file, else the user's keymap.cfg if there is one, else qwerty
+fn load_keymap(name: Option<String>) -> Result<KeyMap, String> {
+    if let Some(name) = name {
+        return KeyMap::load(&name);
+    }
+    match config::config_dir().map(|dir| dir.join("keymap.cfg")) {
+        Some(path) if path.exists() => KeyMap::load(&path.to_string_lossy()),
+        _ => Ok(KeyMap::qwerty()),
+    }
 }
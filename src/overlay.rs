@@ -0,0 +1,141 @@
+use crate::debugger;
+use crate::emu::Chip8;
+use crate::font::glyph;
+
+// The debug overlay: the machine's state as text over the screen, drawn with
+// the built-in font so no font files are needed.
+//
+//   PC 204  I 300  SP 1  DT 00  ST 00
+//   204: 7001  ADD V0, 0x01
+//
+//   V0 05  V1 00  V2 00  V3 00      KEYS
+//   ...                             1 2 3 C
+//                                   ...
+//   STACK
+//   206
+//
+// It's a fixed size, stretched over the screen like the game's frame, and
+// see-through so the game still shows.
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 128;
+
+// a character is 4x5 with a gap after it, and the text is inset by a margin
+pub const CELL_WIDTH: usize = 5;
+pub const CELL_HEIGHT: usize = 7;
+pub const MARGIN: usize = 2;
+
+// ARGB
+const BACKGROUND: u32 = 0xC0000000;
+pub const LABEL: u32 = 0xFF80C0FF;
+pub const VALUE: u32 = 0xFFFFFFFF;
+const KEY_UP: u32 = 0xFF808080;
+const KEY_DOWN: u32 = 0xFFFFCC00;
+
+// the keypad as laid out on the VIP
+static KEYPAD: [[usize; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+const KEYPAD_COLUMN: usize = 40;
+
+// An ARGB image to draw text on, shared with the viewer windows. Drawing is
+// cut off at the edges.
+pub struct Panel {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+}
+
+impl Panel {
+    pub fn new(width: usize, height: usize, background: u32) -> Panel {
+        Panel {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        for row in y..(y + height).min(self.height) {
+            for col in x..(x + width).min(self.width) {
+                self.pixels[row * self.width + col] = color;
+            }
+        }
+    }
+
+    // text at a character cell
+    pub fn text(&mut self, col: usize, row: usize, text: &str, color: u32) {
+        self.text_at(MARGIN + col * CELL_WIDTH, MARGIN + row * CELL_HEIGHT, text, color);
+    }
+
+    // text with its top left at a pixel
+    pub fn text_at(&mut self, x: usize, y: usize, text: &str, color: u32) {
+        for (n, c) in text.chars().enumerate() {
+            let x = x + n * CELL_WIDTH;
+            for (dy, bits) in glyph(c).iter().enumerate() {
+                for dx in 0..4 {
+                    if bits & (0x80 >> dx) != 0 {
+                        self.fill(x + dx, y + dy, 1, 1, color);
+                    }
+                }
+            }
+        }
+    }
+
+    // a label and its value after it
+    fn field(&mut self, col: usize, row: usize, label: &str, value: &str) {
+        self.text(col, row, label, LABEL);
+        self.text(col + label.len() + 1, row, value, VALUE);
+    }
+
+    // as RGBA bytes row by row
+    pub fn rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&argb| {
+                let [b, g, r, a] = argb.to_le_bytes();
+                [r, g, b, a]
+            })
+            .collect()
+    }
+}
+
+// the overlay as RGBA bytes row by row, WIDTH x HEIGHT
+pub fn render(chip8: &Chip8) -> Vec<u8> {
+    let mut panel = Panel::new(WIDTH, HEIGHT, BACKGROUND);
+    panel.field(0, 0, "PC", &format!("{:03x}", chip8.reg_pc));
+    panel.field(8, 0, "I", &format!("{:03x}", chip8.reg_i));
+    panel.field(15, 0, "SP", &chip8.stack_pointer.to_string());
+    panel.field(22, 0, "DT", &format!("{:02x}", chip8.delay_timer));
+    panel.field(29, 0, "ST", &format!("{:02x}", chip8.sound_timer));
+    panel.text(0, 1, &debugger::location(chip8), VALUE);
+
+    for (n, v) in chip8.regs_v.iter().enumerate() {
+        panel.field(n % 4 * 7, 3 + n / 4, &format!("V{:X}", n), &format!("{:02x}", v));
+    }
+
+    panel.text(0, 8, "STACK", LABEL);
+    let stack = &chip8.stack[..chip8.stack_pointer.min(16)];
+    if stack.is_empty() {
+        panel.text(0, 9, "-", VALUE);
+    }
+    for (n, addr) in stack.iter().enumerate() {
+        panel.text(n % 8 * 4, 9 + n / 8, &format!("{:03x}", addr), VALUE);
+    }
+
+    panel.text(KEYPAD_COLUMN, 3, "KEYS", LABEL);
+    for (row, keys) in KEYPAD.iter().enumerate() {
+        for (n, &key) in keys.iter().enumerate() {
+            let col = KEYPAD_COLUMN + n * 2;
+            let color = if chip8.keys[key] {
+                // a pressed key is a dark digit on a lit square
+                let x = MARGIN + col * CELL_WIDTH - 1;
+                let y = MARGIN + (4 + row) * CELL_HEIGHT - 1;
+                panel.fill(x, y, CELL_WIDTH + 1, CELL_HEIGHT, KEY_DOWN);
+                BACKGROUND | 0xFF000000
+            } else {
+                KEY_UP
+            };
+            panel.text(col, 4 + row, &format!("{:X}", key), color);
+        }
+    }
+
+    panel.rgba()
+}
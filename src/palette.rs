@@ -0,0 +1,80 @@
+// Colours for each pixel value. A pixel's value is the set of planes it is
+// lit in (see `Framebuffer::pixel`), so a single plane screen only uses the
+// first two colours, and XO-CHIP's two planes use all four:
+//
+//   0 background, 1 first plane, 2 second plane, 3 both planes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Palette {
+    pub colors: [u32; 4],
+}
+
+pub static THEME_NAMES: [&str; 6] = ["classic", "green", "amber", "lcd", "contrast", "octo"];
+
+impl Palette {
+    pub fn builtin(name: &str) -> Option<Palette> {
+        let colors = match name {
+            "classic" => [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            // P1 phosphor
+            "green" => [0x001A08, 0x33FF66, 0x1A9933, 0xAAFFBB],
+            // P3 phosphor
+            "amber" => [0x1A0F00, 0xFFB000, 0x995C00, 0xFFDD88],
+            // dot matrix handheld
+            "lcd" => [0x9BBC0F, 0x0F380F, 0x8BAC0F, 0x306230],
+            "contrast" => [0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF],
+            // Octo's defaults, which most XO-CHIP games were made with
+            "octo" => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+            _ => return None,
+        };
+        Some(Palette { colors })
+    }
+
+    // Either a theme name or hex RGB colours: `fg,bg`, as for a single plane, or
+    // `fg,bg,fg2,both` to also choose the second plane and overlap colours. With
+    // two colours the second plane is drawn halfway between them.
+    pub fn parse(value: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::builtin(value) {
+            return Ok(palette);
+        }
+        let colors = value
+            .split(',')
+            .map(|s| {
+                let s = s.trim().trim_start_matches('#');
+                match u32::from_str_radix(s, 16) {
+                    Ok(c) if s.len() == 6 => Ok(c),
+                    _ => Err(format!("not a theme or hex RGB colour: {}", s)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match colors[..] {
+            [fg, bg] => Ok(Palette {
+                colors: [bg, fg, blend(fg, bg, 128), fg],
+            }),
+            [fg, bg, fg2, both] => Ok(Palette {
+                colors: [bg, fg, fg2, both],
+            }),
+            _ => Err(format!(
+                "expected a theme ({}) or two or four colours, e.g. 33ff66,000000",
+                THEME_NAMES.join(", ")
+            )),
+        }
+    }
+
+    pub fn color(&self, pixel: u8) -> u32 {
+        self.colors[pixel as usize & 3]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::builtin("classic").unwrap()
+    }
+}
+
+// weight out of 256 for `a`, the rest for `b`
+pub fn blend(a: u32, b: u32, weight: u32) -> u32 {
+    (0..3).fold(0, |color, i| {
+        let shift = i * 8;
+        let channel = (((a >> shift) & 0xFF) * weight + ((b >> shift) & 0xFF) * (256 - weight)) / 256;
+        color | channel << shift
+    })
+}
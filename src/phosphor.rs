@@ -0,0 +1,71 @@
+use crate::framebuffer::Framebuffer;
+use crate::palette::{blend, Palette};
+
+// Simulated phosphor persistence: a pixel that goes dark fades out over a
+// number of rendered frames instead of vanishing, which hides most of the
+// flicker from games erasing and redrawing sprites with XOR. It only looks
+// at successive snapshots of the display, so the machine is unaffected.
+pub struct Phosphor {
+    frames: u32,
+    width: usize,
+    // per pixel: the value it had when last lit, and how many frames it has been dark
+    last: Vec<u8>,
+    age: Vec<u32>,
+    // per row, whether any pixel in it is still fading (and so needs redrawing)
+    fading: Vec<bool>,
+}
+
+impl Phosphor {
+    pub fn new(frames: u32) -> Phosphor {
+        Phosphor {
+            frames,
+            width: 0,
+            last: Vec::new(),
+            age: Vec::new(),
+            fading: Vec::new(),
+        }
+    }
+
+    // takes the next frame's snapshot, noting which rows are still fading
+    pub fn update(&mut self, display: &Framebuffer) {
+        let (width, height) = (display.width(), display.height());
+        if self.width != width || self.age.len() != width * height {
+            self.width = width;
+            self.last = vec![0; width * height];
+            self.age = vec![self.frames + 1; width * height];
+        }
+        self.fading.resize(height, false);
+        for y in 0..height {
+            let mut fading = false;
+            for x in 0..width {
+                let i = y * width + x;
+                let pixel = display.pixel(x, y);
+                if pixel != 0 {
+                    self.last[i] = pixel;
+                    self.age[i] = 0;
+                } else if self.age[i] <= self.frames {
+                    self.age[i] += 1;
+                    fading = true;
+                }
+            }
+            self.fading[y] = fading;
+        }
+    }
+
+    pub fn is_fading(&self, y: usize) -> bool {
+        self.fading.get(y).copied().unwrap_or(false)
+    }
+
+    pub fn color(&self, palette: &Palette, display: &Framebuffer, x: usize, y: usize) -> u32 {
+        let pixel = display.pixel(x, y);
+        let i = y * self.width + x;
+        match self.age.get(i) {
+            Some(&age) if pixel == 0 && age <= self.frames => {
+                // from the lit colour, down to the background one step after the last frame
+                let weight = 256 * (self.frames + 1 - age) / (self.frames + 1);
+                blend(palette.color(self.last[i]), palette.color(0), weight)
+            }
+            _ => palette.color(pixel),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::disasm::disassemble;
+use crate::emu::Chip8;
+use crate::palette::blend;
+use crate::trace::Follower;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Counts the instructions run at each address and of each kind, to find
+// where a ROM spends its time and how much of it ever runs.
+pub struct Profiler {
+    follower: Follower,
+    counts: Vec<u64>,
+    // the opcode last run at each address (it may be self-modifying)
+    opcodes: Vec<u16>,
+    // by the opcode's first nibble
+    classes: [u64; 16],
+    rom_len: usize,
+}
+
+static CLASS_NAMES: [&str; 16] = [
+    "00E0/00EE (CLS, RET)",
+    "1NNN (JP)",
+    "2NNN (CALL)",
+    "3XNN (SE)",
+    "4XNN (SNE)",
+    "5XY0 (SE)",
+    "6XNN (LD)",
+    "7XNN (ADD)",
+    "8XYN (arithmetic)",
+    "9XY0 (SNE)",
+    "ANNN (LD I)",
+    "BNNN (JP V0)",
+    "CXNN (RND)",
+    "DXYN (DRW)",
+    "EXNN (SKP, SKNP)",
+    "FXNN (timers, I, memory)",
+];
+
+// hot spots listed in the report
+const HOT_SPOTS: usize = 20;
+
+impl Profiler {
+    pub fn new(chip8: &Chip8, rom_len: usize) -> Profiler {
+        Profiler {
+            follower: Follower::new(chip8),
+            counts: vec![0; 0x1000],
+            opcodes: vec![0; 0x1000],
+            classes: [0; 16],
+            rom_len,
+        }
+    }
+
+    // called after every cycle
+    pub fn log(&mut self, chip8: &Chip8) {
+        if let Some(ran) = self.follower.update(chip8) {
+            let pc = ran.pc & 0xFFF;
+            self.counts[pc] += 1;
+            self.opcodes[pc] = ran.opcode;
+            self.classes[(ran.opcode >> 12) as usize] += 1;
+        }
+    }
+
+    // how often each byte was part of an instruction that ran
+    fn byte_counts(&self) -> Vec<u64> {
+        let mut bytes = vec![0; 0x1000];
+        for (addr, &count) in self.counts.iter().enumerate() {
+            bytes[addr] = bytes[addr].max(count);
+            bytes[(addr + 1) & 0xFFF] = bytes[(addr + 1) & 0xFFF].max(count);
+        }
+        bytes
+    }
+
+    pub fn report(&self) -> String {
+        let total: u64 = self.counts.iter().sum();
+        let percent = |n: u64| if total == 0 { 0.0 } else { n as f64 * 100.0 / total as f64 };
+        let bytes = self.byte_counts();
+        let covered = bytes[0x200..0x200 + self.rom_len].iter().filter(|&&n| n > 0).count();
+        let mut report = format!("Instructions run: {}\n", total);
+        report.push_str(&format!(
+            "ROM run: {} of {} bytes ({:.1}%)\n",
+            covered,
+            self.rom_len,
+            if self.rom_len == 0 { 0.0 } else { covered as f64 * 100.0 / self.rom_len as f64 }
+        ));
+        report.push_str("\nBy instruction:\n");
+        let mut classes: Vec<usize> = (0..16).filter(|&n| self.classes[n] > 0).collect();
+        classes.sort_by_key(|&n| std::cmp::Reverse(self.classes[n]));
+        for n in classes {
+            report.push_str(&format!(
+                "{:12} {:5.1}%  {}\n",
+                self.classes[n],
+                percent(self.classes[n]),
+                CLASS_NAMES[n]
+            ));
+        }
+        report.push_str("\nHot spots:\n");
+        let mut addrs: Vec<usize> = (0..0x1000).filter(|&a| self.counts[a] > 0).collect();
+        addrs.sort_by_key(|&a| (std::cmp::Reverse(self.counts[a]), a));
+        for addr in addrs.into_iter().take(HOT_SPOTS) {
+            let opcode = self.opcodes[addr];
+            report.push_str(&format!(
+                "{:12} {:5.1}%  {:03x}: {:04x}  {}\n",
+                self.counts[addr],
+                percent(self.counts[addr]),
+                addr,
+                opcode,
+                disassemble(opcode)
+            ));
+        }
+        report
+    }
+
+    // The address space as a 64x64 grid, a pixel per byte (address = y * 64 +
+    // x), in RGB. Bytes that ran go from red to yellow to white, on a log
+    // scale; the rest of the ROM is dark blue.
+    pub fn heatmap(&self) -> Vec<u32> {
+        let bytes = self.byte_counts();
+        let max = (*bytes.iter().max().unwrap_or(&0) as f64).ln_1p();
+        let rom = 0x200..0x200 + self.rom_len;
+        bytes
+            .iter()
+            .enumerate()
+            .map(|(addr, &count)| {
+                if count == 0 {
+                    return if rom.contains(&addr) { 0x202050 } else { 0x000000 };
+                }
+                let heat = if max == 0.0 { 1.0 } else { (count as f64).ln_1p() / max };
+                let weight = (heat * 512.0) as u32;
+                if weight < 256 {
+                    blend(0xFFFF00, 0x800000, weight)
+                } else {
+                    blend(0xFFFFFF, 0xFFFF00, weight - 256)
+                }
+            })
+            .collect()
+    }
+
+    pub fn write_report(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.report()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // the heatmap as a BMP, each byte an 8x8 square
+    pub fn write_heatmap(&self, path: &str) -> Result<(), String> {
+        write_bmp(path, &self.heatmap(), 64, 64, 8).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+// an uncompressed 24-bit BMP of width x height RGB pixels, each scaled up
+fn write_bmp(path: &str, pixels: &[u32], width: usize, height: usize, scale: usize) -> std::io::Result<()> {
+    let (out_width, out_height) = (width * scale, height * scale);
+    // rows are padded to whole words
+    let row_len = (out_width * 3).div_ceil(4) * 4;
+    let size = 54 + row_len * out_height;
+    let mut bmp = Vec::with_capacity(size);
+    bmp.extend_from_slice(b"BM");
+    for n in [size as u32, 0, 54, 40, out_width as u32, out_height as u32] {
+        bmp.extend_from_slice(&n.to_le_bytes());
+    }
+    // one plane, 24 bits per pixel, no compression, default resolution and palette
+    bmp.extend_from_slice(&1u16.to_le_bytes());
+    bmp.extend_from_slice(&24u16.to_le_bytes());
+    for n in [0, (row_len * out_height) as u32, 2835, 2835, 0, 0] {
+        bmp.extend_from_slice(&n.to_le_bytes());
+    }
+    // bottom row first, BGR
+    for y in (0..out_height).rev() {
+        let start = bmp.len();
+        for x in 0..out_width {
+            let [b, g, r, _] = pixels[y / scale * width + x / scale].to_le_bytes();
+            bmp.extend_from_slice(&[b, g, r]);
+        }
+        bmp.resize(start + row_len, 0);
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&bmp)?;
+    file.flush()
+}
@@ -0,0 +1,115 @@
+use crate::filter::Filter;
+use crate::framebuffer::Framebuffer;
+use crate::palette::Palette;
+use crate::phosphor::Phosphor;
+
+// Turns the machine's display into RGBA pixels: palette, then phosphor
+// persistence, then the upscaling filter. It knows nothing about windows, so
+// the SDL frontend, screenshots and tests all get the same picture.
+pub struct Renderer {
+    palette: Palette,
+    filter: Filter,
+    phosphor: Option<Phosphor>,
+    // the screen's colours, kept between frames so only changed rows are recomputed
+    colors: Vec<u32>,
+    width: usize,
+    height: usize,
+    // the filtered image
+    frame: Vec<u32>,
+    // the display's stamp as of the last update, so rows changed since can be found
+    drawn: u64,
+    // set when the palette changes, as every row has to be redone
+    stale: bool,
+}
+
+impl Renderer {
+    // `persistence`: frames for pixels to fade out over, 0 for none
+    pub fn new(palette: Palette, filter: Filter, persistence: u32) -> Renderer {
+        Renderer {
+            palette,
+            filter,
+            phosphor: match persistence {
+                0 => None,
+                frames => Some(Phosphor::new(frames)),
+            },
+            colors: Vec::new(),
+            width: 0,
+            height: 0,
+            frame: Vec::new(),
+            drawn: 0,
+            stale: true,
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.stale = true;
+    }
+
+    // Takes the display's changes (call once per frame shown, as persistence
+    // counts these). Returns whether the picture changed.
+    pub fn update(&mut self, display: &Framebuffer) -> bool {
+        if let Some(phosphor) = self.phosphor.as_mut() {
+            phosphor.update(display);
+        }
+        if self.width != display.width() || self.height != display.height() {
+            self.width = display.width();
+            self.height = display.height();
+            self.colors = vec![0; self.width * self.height];
+            self.stale = true;
+        }
+        // a new palette or size redraws every row (all changed since 0)
+        let since = if self.stale { 0 } else { self.drawn };
+        let mut rows: Vec<usize> = display.rows_changed_since(since).collect();
+        if let Some(phosphor) = &self.phosphor {
+            rows.extend((0..self.height).filter(|&y| phosphor.is_fading(y)));
+            rows.sort_unstable();
+            rows.dedup();
+        }
+        self.stale = false;
+        self.drawn = display.stamp();
+        if rows.is_empty() {
+            return false;
+        }
+        for y in rows {
+            for x in 0..self.width {
+                self.colors[y * self.width + x] = match &self.phosphor {
+                    Some(phosphor) => phosphor.color(&self.palette, display, x, y),
+                    None => self.palette.color(display.pixel(x, y)),
+                };
+            }
+        }
+        self.frame = self.filter.apply(&self.colors, self.width, self.height);
+        true
+    }
+
+    // the size of `frame`: the screen's resolution times the filter's scale
+    pub fn frame_size(&self) -> (usize, usize) {
+        let scale = self.filter.scale();
+        (self.width * scale, self.height * scale)
+    }
+
+    // the filtered image stretched to any size (nearest neighbour), as RGBA
+    // bytes row by row; at `frame_size` it is the image as is. Before the
+    // first `update` there is no image, and it's all background.
+    pub fn render(&self, width: usize, height: usize) -> Vec<u8> {
+        if self.frame.is_empty() {
+            return rgba(self.palette.color(0)).repeat(width * height);
+        }
+        let (frame_width, frame_height) = self.frame_size();
+        let mut out = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let src_y = y * frame_height / height;
+            for x in 0..width {
+                let src_x = x * frame_width / width;
+                out.extend_from_slice(&rgba(self.frame[src_y * frame_width + src_x]));
+            }
+        }
+        out
+    }
+}
+
+fn rgba(color: u32) -> [u8; 4] {
+    let [b, g, r, _] = color.to_le_bytes();
+    [r, g, b, 0xFF]
+}
@@ -1,17 +1,36 @@
 extern crate rand;
-use rand::{OsRng, Rng};
+use rand::{OsRng, Rng, SeedableRng, XorShiftRng};
 
+#[derive(Clone)]
 pub struct RandomBytes {
-    rng: OsRng
+    rng: XorShiftRng
 }
 
 impl RandomBytes {
     pub fn new() -> RandomBytes {
+        let mut os_rng = OsRng::new().expect("Error opening new random number generator");
+        RandomBytes::with_seed(os_rng.gen())
+    }
+    // same seed, same sequence: lets runs be reproduced
+    pub fn with_seed(seed: u64) -> RandomBytes {
+        // splitmix64 spreads small seeds over the whole state (which must not be all zero)
+        let mut state = seed;
+        let mut words = [0u32; 4];
+        for word in words.iter_mut() {
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            *word = (z ^ (z >> 31)) as u32;
+        }
+        if words == [0; 4] {
+            words[0] = 1;
+        }
         RandomBytes {
-            rng: OsRng::new().expect("Error opening new random number generator")
+            rng: XorShiftRng::from_seed(words)
         }
     }
     pub fn next(&mut self) -> u8 {
         self.rng.gen()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,130 @@
+use crate::emu::Chip8Options;
+
+// Known ROMs, identified by the SHA-1 of the file, in the spirit of the
+// community CHIP-8 database (https://github.com/chip-8/chip-8-database).
+//
+// platform: the interpreter the ROM was written for, which decides the
+//           starting quirks (see `RomProfile::options`)
+// quirks:   adjustments on top of the platform's quirks
+// tickrate: recommended instructions per 60 Hz frame
+// keys:     which CHIP-8 keys the game uses, by controller input name
+pub struct RomProfile {
+    pub sha1: &'static str,
+    pub title: &'static str,
+    pub authors: &'static str,
+    pub platform: &'static str,
+    pub quirks: &'static [(&'static str, bool)],
+    pub tickrate: Option<u32>,
+    pub keys: &'static [(&'static str, u8)],
+}
+
+impl RomProfile {
+    pub fn options(&self) -> Chip8Options {
+        let preset = match self.platform {
+            "originalChip8" => "vip",
+            _ => "schip",
+        };
+        let mut options = Chip8Options::preset(preset).unwrap();
+        for (name, enabled) in self.quirks {
+            options.set_quirk(name, *enabled).unwrap();
+        }
+        options
+    }
+
+    pub fn keys_description(&self) -> String {
+        self.keys
+            .iter()
+            .map(|(input, key)| format!("{}={:X}", input, key))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+pub fn lookup(sha1: &str) -> Option<&'static RomProfile> {
+    ROMS.iter().find(|rom| rom.sha1 == sha1)
+}
+
+macro_rules! rom {
+    ($sha1: expr, $title: expr, $authors: expr, $platform: expr, $quirks: expr, $tickrate: expr, $keys: expr) => {
+        RomProfile {
+            sha1: $sha1,
+            title: $title,
+            authors: $authors,
+            platform: $platform,
+            quirks: &$quirks,
+            tickrate: $tickrate,
+            keys: &$keys,
+        }
+    };
+}
+
+// Most of the games in roms/; see the README for the ones left out. Without a
+// tickrate the default speed is used.
+static ROMS: [RomProfile; 33] = [
+    rom!("cf3a8c546038c63cd4cc1de8d171b9bf0d57c0ee", "15 Puzzle", "Roger Ivie", "originalChip8",
+        [], None, []),
+    rom!("fca71182a8838b686573e69b22aff945d79fe1d0", "Airplane", "", "originalChip8",
+        [], None, [("a", 0x8)]),
+    rom!("d40abc54374e4343639f993e897e00904ddf85d9", "Blinky", "Hans Christian Egeberg", "chip48",
+        [], Some(30), [("up", 0x3), ("down", 0x6), ("left", 0x7), ("right", 0x8)]),
+    rom!("6f6509f38220e057a7e32ebb22dd353c1078e3e7", "Blitz", "David Winter", "chip48",
+        [], None, [("a", 0x5)]),
+    rom!("237756a4014fb3aa82a29246a7cdd534f8dc2dbb", "Breakout", "Carmelo Cortez", "originalChip8",
+        [], None, [("left", 0x4), ("right", 0x6)]),
+    rom!("f13766c14aeb02ad8d4d103cb5eadd282d20cddc", "Brix", "Andreas Gustafsson", "chip48",
+        [], None, [("left", 0x4), ("right", 0x6)]),
+    rom!("5c82520906073287a3ef781746c67207ca084d93", "Cave", "", "chip48",
+        [], None, [("up", 0x2), ("down", 0x8), ("left", 0x4), ("right", 0x6), ("a", 0xF)]),
+    rom!("2d10c07b532f4fa7c07a07324ba26ca39fe484fd", "Connect 4", "David Winter", "chip48",
+        [], None, [("left", 0x4), ("right", 0x6), ("a", 0x5)]),
+    rom!("ae71a7b081a947f1760cdc147759803aea45e751", "Filter", "", "chip48",
+        [], None, [("left", 0x4), ("right", 0x6)]),
+    rom!("137cb8397456f53fcab216124458238bc18c0965", "Guess", "David Winter", "chip48",
+        [], None, [("a", 0x5)]),
+    rom!("050f07a54371da79f924dd0227b89d07b4f2aed0", "Hidden", "David Winter", "chip48",
+        [], None, [("up", 0x2), ("down", 0x8), ("left", 0x4), ("right", 0x6), ("a", 0x5)]),
+    rom!("5c28a5f85289c9d859f95fd5eadbdcb1c30bb08b", "Space Invaders", "David Winter", "chip48",
+        [], None, [("left", 0x4), ("right", 0x6), ("a", 0x5)]),
+    rom!("d6fa9dc9005dc0496f39ba52fef56f9fd0a5a158", "Kaleidoscope", "Joseph Weisbecker", "originalChip8",
+        [], None, [("up", 0x2), ("down", 0x8), ("left", 0x4), ("right", 0x6), ("a", 0x0)]),
+    rom!("72fb3e0a4572bdb81f484df7948a8bc736fe78d0", "Landing", "", "chip48",
+        [], None, [("a", 0x8)]),
+    rom!("8b70080adbac44513ec60005734a816372b845ec", "Maze", "David Winter", "chip48",
+        [], None, []),
+    rom!("d979858bb9ffd07b48f52f92a8bcac0199f3623e", "Merlin", "David Winter", "chip48",
+        [], None, [("up", 0x4), ("right", 0x5), ("left", 0x7), ("down", 0x8)]),
+    rom!("0d0cc129dad3c45ba672f85fec71a668232212cc", "Missile Command", "David Winter", "chip48",
+        [], None, [("a", 0x8)]),
+    rom!("b232ef880bd6060fb45fa6effed7edf0ae95670e", "Pong", "Paul Vervalin", "chip48",
+        [], None, [("up", 0x1), ("down", 0x4)]),
+    rom!("607c4f7f4e4dce9f99d96b3182bfe7e88bb090ee", "Pong (1 player)", "Paul Vervalin", "chip48",
+        [], None, [("up", 0x1), ("down", 0x4)]),
+    rom!("1830eb401ba8789a477dfcf294873a5479ebcfe8", "Pong 2", "David Winter", "chip48",
+        [], None, [("up", 0x1), ("down", 0x4)]),
+    rom!("5e70f91ca08e9b9e9de61670492e3db2d7f7d57a", "Rocket", "Joseph Weisbecker", "originalChip8",
+        [], None, [("a", 0xF)]),
+    rom!("aa4f1a282bd64a2364102abf5737a4205365a2b4", "Space Flight", "", "chip48",
+        [], None, [("up", 0x1), ("down", 0x4), ("a", 0xF)]),
+    rom!("a58ec7cc63707f9e7274026de27c15ec1d9945bd", "Squash", "David Winter", "chip48",
+        [], None, [("up", 0x1), ("down", 0x4)]),
+    rom!("1bdb4ddaa7049266fa3226851f28855a365cfd12", "Syzygy", "Roy Trevino", "chip48",
+        [], Some(15), [("up", 0x3), ("down", 0x6), ("left", 0x7), ("right", 0x8), ("a", 0xF)]),
+    rom!("18b9d15f4c159e1f0ed58c2d8ec1d89325d3a3b6", "Tank", "", "originalChip8",
+        [], None, [("up", 0x8), ("down", 0x2), ("left", 0x4), ("right", 0x6), ("a", 0x5)]),
+    rom!("5f518084744bf3cb8733f6e5454dfd1634320563", "Tetris", "Fran Dachille", "chip48",
+        [], None, [("a", 0x4), ("left", 0x5), ("right", 0x6), ("down", 0x7)]),
+    rom!("429d455a4bc53167942bf6fd934d72b0f648dce3", "Tic-Tac-Toe", "David Winter", "chip48",
+        [], None, []),
+    rom!("bdb92475acfe11bc7814a2f5eade13fcd09b756a", "UFO", "Lutz V", "chip48",
+        [], None, [("left", 0x4), ("up", 0x5), ("right", 0x6)]),
+    rom!("da710f631f8e35534d0b9170bcf892a60f49c43d", "Vertical Brix", "Paul Robson", "chip48",
+        [], None, [("up", 0x1), ("down", 0x4), ("a", 0x7)]),
+    rom!("ade839585ddeb0e3633177df03c1d91589e629eb", "Vers", "JMN", "chip48",
+        [], None, [("up", 0x7), ("down", 0xA), ("left", 0x1), ("right", 0x2)]),
+    rom!("09ce01c54ddddda42ca5cd171f1ffcfd47355d12", "Wall", "David Winter", "chip48",
+        [], None, [("up", 0x1), ("down", 0x4)]),
+    rom!("d666688a8fce468a7d88b536bc1ef5f35ba12031", "Wipe Off", "Joseph Weisbecker", "originalChip8",
+        [], None, [("left", 0x4), ("right", 0x6)]),
+    rom!("f1cfcffe1937ed6dd6eeed1a7f85dfc777bda700", "Test Opcode", "corax89", "chip48",
+        [], None, []),
+];
@@ -0,0 +1,101 @@
+use crate::config::{config_dir, Config};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// Settings changed while playing a ROM, remembered in settings.cfg in the
+// config directory with one section per ROM hash:
+//
+//   [f13766c14aeb02ad8d4d103cb5eadd282d20cddc]
+//   rom = BRIX.ch8
+//   ips = 600
+//   keymap = azerty
+//
+// They sit between the command line (which wins) and the ROM's profile.
+// Settings at the top of the file, before any section, apply to every ROM
+// that doesn't have its own, below the ROM's profile:
+//
+//   palette = amber
+#[derive(Clone, Default)]
+pub struct RomSettings {
+    pub ips: Option<f64>,
+    pub palette: Option<String>,
+    pub keymap: Option<String>,
+}
+
+pub struct Settings {
+    // None when there is no config directory, in which case nothing is saved
+    path: Option<PathBuf>,
+    roms: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Settings {
+    pub fn load() -> Result<Settings, String> {
+        let mut settings = Settings {
+            path: config_dir().map(|dir| dir.join("settings.cfg")),
+            roms: BTreeMap::new(),
+        };
+        if let Some(path) = settings.path.as_ref().filter(|p| p.exists()) {
+            let config = Config::load(path)?;
+            for entry in config.entries() {
+                settings
+                    .roms
+                    .entry(entry.section.clone())
+                    .or_default()
+                    .insert(entry.key.clone(), entry.value.clone());
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn rom(&self, sha1: &str) -> RomSettings {
+        let values = match self.roms.get(sha1) {
+            Some(values) => values,
+            None => return RomSettings::default(),
+        };
+        RomSettings {
+            ips: values.get("ips").and_then(|v| v.parse().ok()),
+            palette: values.get("palette").cloned(),
+            keymap: values.get("keymap").cloned(),
+        }
+    }
+
+    // the settings for ROMs without their own
+    pub fn defaults(&self) -> RomSettings {
+        self.rom("")
+    }
+
+    // records the settings for a ROM and writes the file straight away
+    pub fn set_rom(&mut self, sha1: &str, rom_file: &str, rom: &RomSettings) -> Result<(), String> {
+        let values = self.roms.entry(sha1.to_string()).or_default();
+        values.insert("rom".to_string(), rom_file.to_string());
+        let mut set = |key: &str, value: Option<String>| match value {
+            Some(value) => values.insert(key.to_string(), value),
+            None => values.remove(key),
+        };
+        set("ips", rom.ips.map(|ips| ips.to_string()));
+        set("palette", rom.palette.clone());
+        set("keymap", rom.keymap.clone());
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut text = String::from("# per-ROM settings, written by chip8\n");
+        for (sha1, values) in &self.roms {
+            // the defaults come first (as "" sorts first), outside any section
+            if !sha1.is_empty() {
+                text.push_str(&format!("\n[{}]\n", sha1));
+            }
+            for (key, value) in values {
+                text.push_str(&format!("{} = {}\n", key, value));
+            }
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
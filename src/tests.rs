@@ -158,6 +158,1051 @@ mod tests {
 
 
 
+    #[test]
+    fn display_wait_test() {
+        let mut tr = Chip8Tester::with_options(crate::emu::Chip8Options {
+            display_wait: true,
+            ..Default::default()
+        });
+        tr.load_multiple(vec![0xD001, 0xD001, 0x6005], 0x200);
+
+        tr.v.cycle();
+        assert_eq!(tr.pc(), 0x202);
+        assert!(tr.v.awaiting_vblank);
+        // blocked until the next tick
+        tr.v.cycle();
+        tr.v.cycle();
+        assert_eq!(tr.pc(), 0x202);
+        assert_eq!(tr.v.opcode(), 0xD001);
+        // the wait is part of the machine state, so snapshots carry it
+        let snapshot = tr.v.clone();
+        assert!(snapshot.awaiting_vblank);
+
+        tr.v.tick();
+        tr.v.cycle();
+        assert_eq!(tr.pc(), 0x204);
+        tr.v.cycle();
+        assert_eq!(tr.pc(), 0x204);
+
+        // without the quirk draws don't wait
+        let mut tr = Chip8Tester::new();
+        tr.load_multiple(vec![0xD001, 0xD001], 0x200);
+        tr.v.cycle();
+        tr.v.cycle();
+        assert_eq!(tr.pc(), 0x204);
+    }
+
+    #[test]
+    fn cli_test() {
+        use crate::cli::{parse, Command};
+
+        let args = |s: &str| -> Vec<String> { s.split_whitespace().map(String::from).collect() };
+
+        match parse(&args("game.ch8 --speed 2 --quirk shiftVyNotVx --seed=7")) {
+            Ok(Command::Run(o)) => {
+                assert_eq!(o.rom, "game.ch8");
+                assert_eq!(o.ips(None), 960.0);
+                assert_eq!(o.seed, Some(7));
+                assert!(o.chip8_options(None).shift_vy_not_vx);
+                assert!(!o.chip8_options(None).saving_increases_reg_i);
+            }
+            _ => panic!("expected run"),
+        }
+
+        match parse(&args("headless game.ch8 --preset vip --quirk shiftVyNotVx=off --frames 10")) {
+            Ok(Command::Headless(o, frames)) => {
+                assert_eq!(frames, 10);
+                assert!(o.chip8_options(None).saving_increases_reg_i);
+                assert!(!o.chip8_options(None).shift_vy_not_vx);
+            }
+            _ => panic!("expected headless"),
+        }
+
+        assert!(parse(&args("game.ch8 --speed 2 --ips 500")).is_err());
+        assert!(parse(&args("game.ch8 --quirk nope")).is_err());
+        assert!(parse(&args("game.ch8 --frames 10")).is_err());
+        assert!(parse(&args("info game.ch8 --speed 2")).is_err());
+        assert!(parse(&args("game.ch8 --speed")).is_err());
+        assert!(matches!(parse(&args("game.ch8 --scaling integer")), Ok(Command::Run(o)) if o.integer_scaling));
+        assert!(parse(&args("game.ch8 --scaling stretch")).is_err());
+        assert!(matches!(parse(&args("game.ch8 --debug --scale 2")), Ok(Command::Run(o)) if o.debug && o.scale == 2));
+        assert!(matches!(parse(&args("debug game.ch8")), Ok(Command::Debug(_))));
+        assert!(matches!(parse(&args("disasm game.ch8")), Ok(Command::Disasm(_, None))));
+        assert!(matches!(parse(&args("disasm game.ch8 --access-map m.txt")), Ok(Command::Disasm(_, Some(m))) if m == "m.txt"));
+        assert!(parse(&args("info game.ch8 --access-map m.txt")).is_err());
+        match parse(&args("headless game.ch8 --trace t.log --trace-pc 200-2ff --trace-cycles 1000-")) {
+            Ok(Command::Headless(o, _)) => {
+                assert_eq!(o.trace.as_deref(), Some("t.log"));
+                assert_eq!(o.trace_pc, Some(0x200..=0x2FF));
+                assert_eq!(o.trace_cycles, Some(1000..=u64::MAX));
+            }
+            _ => panic!("expected headless"),
+        }
+        assert!(parse(&args("game.ch8 --trace t.log --trace-pc 300-200")).is_err());
+        assert!(parse(&args("game.ch8 --trace t.log --trace-pc 1000-1fff")).is_err());
+        assert!(parse(&args("game.ch8 --trace t.log --trace-pc 200-1000")).is_err());
+        assert!(matches!(parse(&args("game.ch8 --trace t.log --trace-pc 800-")), Ok(Command::Run(o)) if o.trace_pc == Some(0x800..=0xFFF)));
+        assert!(parse(&args("game.ch8 --trace-cycles 5-")).is_err());
+        assert!(matches!(parse(&args("tracediff a.log b.log --context 3")), Ok(Command::TraceDiff(a, b, 3)) if a == "a.log" && b == "b.log"));
+        assert!(parse(&args("tracediff a.log")).is_err());
+        assert!(matches!(parse(&args("game.ch8 --profile p.txt --heatmap h.bmp")),
+            Ok(Command::Run(o)) if o.profile.as_deref() == Some("p.txt") && o.heatmap.as_deref() == Some("h.bmp")));
+        assert!(parse(&args("game.ch8 other.ch8")).is_err());
+    }
+
+    #[test]
+    fn settings_order_test() {
+        use crate::cli::{parse, Command};
+        use crate::romdb::RomProfile;
+        use crate::settings::RomSettings;
+
+        let options = |args: &str| match parse(&args.split_whitespace().map(String::from).collect::<Vec<_>>()) {
+            Ok(Command::Run(o)) => o,
+            _ => panic!("expected run"),
+        };
+        let profile = RomProfile {
+            sha1: "",
+            title: "Test",
+            authors: "",
+            platform: "originalChip8",
+            quirks: &[],
+            tickrate: Some(15),
+            keys: &[],
+        };
+        let saved = RomSettings { ips: Some(500.0), palette: None, keymap: Some("azerty".to_string()) };
+        let defaults = RomSettings {
+            ips: Some(700.0),
+            palette: Some("amber".to_string()),
+            keymap: Some("qwerty".to_string()),
+        };
+        let none = RomSettings::default();
+
+        // the command line, then the ROM's own settings, then its profile, then the defaults
+        let mut o = options("game.ch8 --ips 800");
+        o.fill_in(&saved, Some(&profile), &defaults);
+        assert_eq!((o.ips(Some(&profile)), o.keymap.as_deref()), (800.0, Some("azerty")));
+        let mut o = options("game.ch8");
+        o.fill_in(&saved, Some(&profile), &defaults);
+        assert_eq!((o.ips(Some(&profile)), o.palette.as_deref()), (500.0, Some("amber")));
+        let mut o = options("game.ch8");
+        o.fill_in(&none, Some(&profile), &defaults);
+        assert_eq!(o.ips(Some(&profile)), 900.0);
+        let mut o = options("game.ch8");
+        o.fill_in(&none, None, &defaults);
+        assert_eq!(o.ips(None), 700.0);
+    }
+
+    #[test]
+    fn romdb_test() {
+        use crate::cli::{parse, Command};
+        use crate::romdb;
+        use crate::sha1::sha1_hex;
+
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            sha1_hex(&[b'a'; 1000]),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+
+        let brix = romdb::lookup(&sha1_hex(include_bytes!("../roms/BRIX.ch8"))).unwrap();
+        assert_eq!(brix.title, "Brix");
+        let kaleid = romdb::lookup(&sha1_hex(include_bytes!("../roms/KALEID.ch8"))).unwrap();
+
+        // the profile applies unless the command line says otherwise
+        let args = |s: &str| -> Vec<String> { s.split_whitespace().map(String::from).collect() };
+        match parse(&args("KALEID.ch8")) {
+            Ok(Command::Run(o)) => {
+                assert!(o.chip8_options(Some(kaleid)).shift_vy_not_vx);
+                assert_eq!(o.ips(Some(kaleid)), crate::cli::DEFAULT_IPS);
+            }
+            _ => panic!("expected run"),
+        }
+        match parse(&args("KALEID.ch8 --preset schip --ips 600")) {
+            Ok(Command::Run(o)) => {
+                assert!(!o.chip8_options(Some(kaleid)).shift_vy_not_vx);
+                assert_eq!(o.ips(Some(kaleid)), 600.0);
+            }
+            _ => panic!("expected run"),
+        }
+    }
+
+    #[test]
+    fn pacer_test() {
+        use crate::timing::{Pacer, Timing};
+        use std::time::Duration;
+
+        // 500 instructions per second is 8 1/3 per frame
+        let mut tr = Chip8Tester::new();
+        tr.load_multiple(vec![0x1200], 0x200);
+        tr.v.delay_timer = 255;
+        let mut pacer = Pacer::new(500.0, Timing::Ips);
+        let mut cycles = 0;
+        for _ in 0..144 {
+            cycles += pacer.advance(&mut tr.v, Duration::from_nanos(1_000_000_000 / 144));
+        }
+        cycles += pacer.advance(&mut tr.v, Duration::from_nanos(1_000_000_000 % 144));
+        assert_eq!(cycles, 500);
+        assert_eq!(tr.v.delay_timer, 255 - 60);
+
+        // a long stall is not made up for all at once
+        let cycles = pacer.advance(&mut tr.v, Duration::from_secs(10));
+        assert!(cycles < 500);
+
+        // with VIP timing a frame has 2644 machine cycles, enough for 51 jumps at 52 each
+        let mut pacer = Pacer::new(500.0, Timing::Vip);
+        assert_eq!(pacer.run_frame(&mut tr.v), 51);
+
+        // a 15 row sprite and a screen clear take up a whole frame, and the
+        // clear runs 282 cycles into the next one
+        tr.reset();
+        tr.load_multiple(vec![0xD00F, 0x00E0, 0x1204, 0x1204], 0x200);
+        let mut pacer = Pacer::new(500.0, Timing::Vip);
+        assert_eq!(pacer.run_frame(&mut tr.v), 2);
+        assert_eq!(pacer.run_frame(&mut tr.v), 46);
+    }
+
+    #[test]
+    fn framebuffer_test() {
+        use crate::framebuffer::Framebuffer;
+
+        let mut fb = Framebuffer::new(64, 32, 1);
+        assert_eq!(fb.rows_changed_since(0).count(), 32);
+        let stamp = fb.stamp();
+        assert_eq!(fb.rows_changed_since(stamp).count(), 0);
+
+        assert!(!fb.toggle(0, 63, 5));
+        assert!(fb.get(63, 5));
+        assert_eq!(fb.rows_changed_since(stamp).collect::<Vec<_>>(), vec![5]);
+        assert!(fb.toggle(0, 63, 5));
+        assert!(!fb.get(63, 5));
+
+        // clearing an empty screen has nothing to redraw
+        let stamp = fb.stamp();
+        fb.clear();
+        assert_eq!(fb.rows_changed_since(stamp).count(), 0);
+        // a copy put back marked dirty is newer than anything drawn since it was taken
+        let copy = fb.clone();
+        fb.toggle(0, 0, 0);
+        let stamp = fb.stamp();
+        fb = copy;
+        fb.mark_dirty();
+        assert_eq!(fb.rows_changed_since(stamp).count(), 32);
+
+        let mut fb = Framebuffer::new(128, 64, 2);
+        fb.toggle(1, 100, 40);
+        assert_eq!(fb.pixel(100, 40), 0b10);
+        assert_eq!(fb.iter().filter(|&(_, _, p)| p != 0).count(), 1);
+
+        // sprites wrap around both edges, and erasing a pixel sets VF
+        let mut tr = Chip8Tester::new();
+        tr.v.memory[0x300] = 0xFF;
+        tr.v.memory[0x301] = 0x81;
+        tr.v.reg_i = 0x300;
+        let stamp = tr.v.display.stamp();
+        tr.v.regs_v[0] = 60;
+        tr.v.regs_v[1] = 31;
+        tr.instr(0xD012);
+        assert_eq!(tr.vf(), 0);
+        assert!(tr.v.display.get(60, 31) && tr.v.display.get(3, 31));
+        assert!(tr.v.display.get(60, 0) && !tr.v.display.get(61, 0) && tr.v.display.get(3, 0));
+        assert_eq!(tr.v.display.rows_changed_since(stamp).collect::<Vec<_>>(), vec![0, 31]);
+        tr.instr(0xD012);
+        assert_eq!(tr.vf(), 1);
+        assert!(!tr.v.display.get(60, 31));
+    }
+
+    #[test]
+    fn palette_test() {
+        use crate::palette::{Palette, THEME_NAMES};
+
+        for name in THEME_NAMES.iter() {
+            assert!(Palette::builtin(name).is_some());
+        }
+        assert_eq!(Palette::parse("amber"), Ok(Palette::builtin("amber").unwrap()));
+        // fg,bg as before, with the second plane halfway between
+        let palette = Palette::parse("33ff66,#000000").unwrap();
+        assert_eq!(palette.colors, [0x000000, 0x33FF66, 0x197F33, 0x33FF66]);
+        assert_eq!(palette.color(1), 0x33FF66);
+        let palette = Palette::parse("ffffff,000000,ff0000,00ff00").unwrap();
+        assert_eq!(palette.color(2), 0xFF0000);
+        assert_eq!(palette.color(3), 0x00FF00);
+        assert!(Palette::parse("ffffff").is_err());
+        assert!(Palette::parse("fff,000").is_err());
+        assert!(Palette::parse("purple").is_err());
+    }
+
+    #[test]
+    fn phosphor_test() {
+        use crate::framebuffer::Framebuffer;
+        use crate::palette::Palette;
+        use crate::phosphor::Phosphor;
+
+        let palette = Palette::default();
+        let mut display = Framebuffer::new(64, 32, 1);
+        let mut phosphor = Phosphor::new(3);
+        display.toggle(0, 10, 4);
+        phosphor.update(&display);
+        assert_eq!(phosphor.color(&palette, &display, 10, 4), 0xFFFFFF);
+
+        // erased pixels fade out over three frames and then stop being redrawn
+        display.toggle(0, 10, 4);
+        let mut colors = Vec::new();
+        let stamp = display.stamp();
+        for _ in 0..5 {
+            phosphor.update(&display);
+            colors.push((phosphor.is_fading(4), phosphor.color(&palette, &display, 10, 4)));
+        }
+        assert_eq!(
+            colors,
+            vec![
+                (true, 0xBFBFBF),
+                (true, 0x7F7F7F),
+                (true, 0x3F3F3F),
+                (true, 0x000000),
+                (false, 0x000000),
+            ]
+        );
+        // without touching the display
+        assert_eq!(display.rows_changed_since(stamp).count(), 0);
+    }
+
+    #[test]
+    fn filter_test() {
+        use crate::filter::{Filter, FILTER_NAMES};
+
+        for name in FILTER_NAMES.iter() {
+            assert!(Filter::from_name(name).is_some());
+        }
+        let (w, b) = (0xFFFFFF, 0x000000);
+        let src = [w, b, w, w];
+        assert_eq!(Filter::None.apply(&src, 2, 2), src.to_vec());
+
+        // the step in the corner gets smoothed over
+        #[rustfmt::skip]
+        assert_eq!(Filter::Scale2x.apply(&src, 2, 2), vec![
+            w, w, b, b,
+            w, w, w, b,
+            w, w, w, w,
+            w, w, w, w,
+        ]);
+        let out = Filter::Scale3x.apply(&src, 2, 2);
+        assert_eq!(out.len(), 36);
+        assert_eq!(&out[3..6], &[b, b, b]);
+        assert_eq!(&out[9..12], &[w, b, b]);
+
+        let out = Filter::Scanlines.apply(&[w], 1, 1);
+        assert_eq!(out, vec![w, w, w, w, w, w, 0x5F5F5F, 0x5F5F5F, 0x5F5F5F]);
+    }
+
+    #[test]
+    fn renderer_test() {
+        use crate::filter::Filter;
+        use crate::framebuffer::Framebuffer;
+        use crate::palette::Palette;
+        use crate::renderer::Renderer;
+
+        let mut display = Framebuffer::new(64, 32, 1);
+        display.toggle(0, 1, 0);
+        let mut renderer = Renderer::new(Palette::default(), Filter::None, 0);
+        assert_eq!(renderer.render(2, 1), [0, 0, 0, 0xFF, 0, 0, 0, 0xFF]);
+        assert!(renderer.update(&display));
+        assert_eq!(renderer.frame_size(), (64, 32));
+        let frame = renderer.render(64, 32);
+        assert_eq!(frame.len(), 64 * 32 * 4);
+        assert_eq!(&frame[..8], &[0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        // nothing changed, nothing to do, and the display is left as it was
+        let stamp = display.stamp();
+        assert!(!renderer.update(&display));
+        assert_eq!(display.stamp(), stamp);
+        display.toggle(0, 1, 0);
+        assert!(renderer.update(&display));
+        assert_eq!(&renderer.render(64, 32)[..8], &[0, 0, 0, 0xFF, 0, 0, 0, 0xFF]);
+        display.toggle(0, 1, 0);
+        assert!(renderer.update(&display));
+
+        // stretched to twice the size, the pixel covers a 2x2 block
+        let frame = renderer.render(128, 64);
+        let lit = |x: usize, y: usize| frame[(y * 128 + x) * 4] == 0xFF;
+        assert!(!lit(1, 0) && lit(2, 0) && lit(3, 1) && !lit(4, 1) && !lit(2, 2));
+
+        renderer.set_palette(Palette::parse("ff0000,0000ff").unwrap());
+        assert!(renderer.update(&display));
+        assert_eq!(&renderer.render(64, 32)[..8], &[0, 0, 0xFF, 0xFF, 0xFF, 0, 0, 0xFF]);
+
+        let mut renderer = Renderer::new(Palette::default(), Filter::Scale3x, 0);
+        renderer.update(&display);
+        assert_eq!(renderer.frame_size(), (192, 96));
+    }
+
+    #[test]
+    fn keymap_test() {
+        use crate::config::Config;
+        use crate::keymap::{KeyMap, Keyboard, LAYOUT_NAMES};
+        use sdl2::keyboard::Keycode;
+
+        for name in LAYOUT_NAMES {
+            assert!(KeyMap::builtin(name).is_some(), "{}", name);
+        }
+        assert!(KeyMap::builtin("nope").is_none());
+        let qwerty = KeyMap::qwerty();
+        assert_eq!(qwerty.get(Keycode::Num1), Some(0x1));
+        assert_eq!(qwerty.get(Keycode::X), Some(0x0));
+        assert_eq!(qwerty.get(Keycode::V), Some(0xF));
+        assert_eq!(qwerty.get(Keycode::G), None);
+        // the same physical keys on other layouts
+        let colemak = KeyMap::builtin("colemak").unwrap();
+        assert_eq!(colemak.get(Keycode::V), Some(0xF));
+        assert_eq!(colemak.get(Keycode::D), None);
+        assert_eq!(colemak.get(Keycode::F), Some(0x6));
+        let azerty = KeyMap::builtin("azerty").unwrap();
+        assert_eq!(azerty.get(Keycode::A), Some(0x4));
+        assert_eq!(azerty.get(Keycode::W), Some(0xA));
+
+        // a file rebinds keys on top of a layout, to several host keys
+        let config = Config::parse("layout = azerty\n5 = Z, Up\n0xA = \n").unwrap();
+        let map = KeyMap::from_config(&config).unwrap();
+        assert_eq!(map.get(Keycode::Z), Some(0x5));
+        assert_eq!(map.get(Keycode::Up), Some(0x5));
+        assert_eq!(map.get(Keycode::W), None);
+        assert_eq!(map.get(Keycode::Num1), Some(0x1));
+        let map = KeyMap::from_config(&Config::parse("5 = Up").unwrap()).unwrap();
+        assert_eq!(map.get(Keycode::W), None);
+        assert_eq!(map.get(Keycode::Q), Some(0x4));
+        for bad in ["layout = nope", "g = Z", "10 = Z", "5 = NoSuchKey"] {
+            assert!(KeyMap::from_config(&Config::parse(bad).unwrap()).is_err(), "{}", bad);
+        }
+
+        // a key with two host keys is down until both are let go
+        let mut keyboard = Keyboard::new(KeyMap::from_config(&Config::parse("5 = Z, Up").unwrap()).unwrap());
+        keyboard.key_event(Keycode::Z, true);
+        keyboard.key_event(Keycode::Up, true);
+        keyboard.key_event(Keycode::Z, false);
+        assert!(keyboard.pressed()[5]);
+        keyboard.key_event(Keycode::Up, false);
+        assert!(!keyboard.pressed()[5]);
+    }
+
+    #[test]
+    fn gamepad_test() {
+        use crate::config::Config;
+        use crate::gamepad::{PadInput, PadMap};
+
+        let map = PadMap::default();
+        assert_eq!(map.get(PadInput::Up), Some(0x2));
+        assert_eq!(map.get(PadInput::A), Some(0x5));
+        assert_eq!(map.get(PadInput::Start), None);
+        assert!(PadInput::from_name("LeftShoulder").is_some());
+        assert!(PadInput::from_name("select").is_none());
+
+        // everywhere, then the ROM's hints, then the ROM's own section
+        let config = Config::parse("start = 0xF\na = 1\n[BRIX]\nleft = 4\nright = none\n").unwrap();
+        let map = PadMap::from_config(&config, "BRIX", &[("right", 0x6), ("a", 0x5)]).unwrap();
+        assert_eq!(map.get(PadInput::Left), Some(0x4));
+        assert_eq!(map.get(PadInput::Right), None);
+        assert_eq!(map.get(PadInput::A), Some(0x5));
+        // hints replace the defaults and the entries for every ROM
+        assert_eq!(map.get(PadInput::Up), None);
+        assert_eq!(map.get(PadInput::Start), None);
+        let map = PadMap::from_config(&config, "PONG", &[]).unwrap();
+        assert_eq!(map.get(PadInput::Start), Some(0xF));
+        assert_eq!(map.get(PadInput::A), Some(0x1));
+        assert_eq!(map.get(PadInput::Up), Some(0x2));
+        assert_eq!(map.get(PadInput::Right), Some(0x6));
+
+        for bad in ["select = 1", "a = 10", "a = g", "[BRIX]\nb = -"] {
+            assert!(PadMap::from_config(&Config::parse(bad).unwrap(), "BRIX", &[]).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn debugger_test() {
+        use crate::debugger::{Action, Debugger, Target};
+
+        let mut tr = Chip8Tester::new();
+        // LD V0, 5; CALL 208; ADD V0, 1; JP 206; LD V1, 3; RET
+        tr.load_multiple(vec![0x6005, 0x2208, 0x7001, 0x1206, 0x6103, 0x00EE], 0x200);
+        let mut dbg = Debugger::new();
+        let run = |tr: &mut Chip8Tester, dbg: &mut Debugger| {
+            for _ in 0..1000 {
+                tr.v.cycle();
+                if dbg.check(&tr.v) {
+                    return true;
+                }
+            }
+            false
+        };
+
+        assert_eq!(dbg.command(&mut tr.v, "s"), Ok(Action::Stepped));
+        assert_eq!((tr.pc(), tr.v0()), (0x202, 5));
+        // next runs the whole call
+        assert_eq!(dbg.command(&mut tr.v, "next"), Ok(Action::Continue(None)));
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!((tr.pc(), tr.v.stack_pointer, tr.v1()), (0x204, 0, 3));
+
+        // into the call, then finish it
+        assert!(dbg.command(&mut tr.v, "finish").is_err());
+        dbg.command(&mut tr.v, "set pc 0x202").unwrap();
+        dbg.command(&mut tr.v, "step").unwrap();
+        assert_eq!((tr.pc(), tr.v.stack_pointer), (0x208, 1));
+        assert_eq!(dbg.command(&mut tr.v, "f"), Ok(Action::Continue(None)));
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!((tr.pc(), tr.v.stack_pointer), (0x204, 0));
+
+        // breakpoints fire on every arrival, even by a jump to the same place
+        dbg.command(&mut tr.v, "break 0x206").unwrap();
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!((tr.pc(), tr.v0()), (0x206, 6));
+        let cycles = tr.v.cycles;
+        assert_eq!(dbg.command(&mut tr.v, "c"), Ok(Action::Continue(None)));
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!((tr.pc(), tr.v.cycles), (0x206, cycles + 1));
+        dbg.command(&mut tr.v, "delete 1").unwrap();
+        assert!(dbg.command(&mut tr.v, "delete 1").is_err());
+        assert!(!run(&mut tr, &mut dbg));
+
+        // watchpoints fire on changes made by the program, not by the debugger
+        dbg.command(&mut tr.v, "watch [0x300]").unwrap();
+        dbg.command(&mut tr.v, "set [0x300] 7").unwrap();
+        assert_eq!(tr.v.memory[0x300], 7);
+        dbg.command(&mut tr.v, "set i 0x300").unwrap();
+        dbg.command(&mut tr.v, "set pc 0x210").unwrap();
+        tr.v.memory[0x210] = 0xF0;
+        tr.v.memory[0x211] = 0x55; // LD [I], V0
+        assert_eq!(dbg.command(&mut tr.v, "c"), Ok(Action::Continue(None)));
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!(tr.pc(), 0x212);
+
+        // an empty line repeats the last command
+        dbg.command(&mut tr.v, "set pc 0x200").unwrap();
+        dbg.command(&mut tr.v, "s").unwrap();
+        dbg.command(&mut tr.v, "").unwrap();
+        assert_eq!(tr.pc(), 0x208);
+
+        assert!(dbg.command(&mut tr.v, "set v3 256").is_err());
+        assert!(dbg.command(&mut tr.v, "set i 0x1000").is_err());
+        assert!(dbg.command(&mut tr.v, "set pc 0xfff").is_err());
+        // lengths past the end of memory stop there
+        assert_eq!(dbg.command(&mut tr.v, "mem 0 0xffffffffffffffff"), Ok(Action::None));
+        assert_eq!(dbg.command(&mut tr.v, "disasm 0xffe 0xffffffffffffffff"), Ok(Action::None));
+        assert!(dbg.command(&mut tr.v, "jump 0x200").is_err());
+        assert_eq!(dbg.command(&mut tr.v, "c 10"), Ok(Action::Continue(Some(10))));
+        assert_eq!(Target::parse("VF"), Ok(Target::V(15)));
+        assert_eq!(Target::parse("[0x1234]"), Ok(Target::Mem(0x234)));
+        assert!(Target::parse("vg").is_err());
+    }
+
+    #[test]
+    fn condition_test() {
+        use crate::debugger::Debugger;
+        use crate::expr::Expr;
+
+        let mut tr = Chip8Tester::new();
+        tr.v.regs_v[3] = 0x10;
+        tr.v.reg_i = 0x300;
+        tr.v.memory[0x300] = 2;
+        let eval = |tr: &Chip8Tester, s: &str| Expr::parse(s).unwrap().eval(&tr.v);
+        assert_eq!(eval(&tr, "v3 == 0x10 && [i] != 0"), 1);
+        assert_eq!(eval(&tr, "[i + 0] + 1 == 3 || vf"), 1);
+        assert_eq!(eval(&tr, "v3 & 0xf0 | 1"), 0x11);
+        assert_eq!(eval(&tr, "!(v3 > 0x10) && v3 >= 16 && dt < 1"), 1);
+        assert_eq!(eval(&tr, "1 - 2 + 2"), 1);
+        assert!(Expr::parse("v3 ==").is_err());
+        assert!(Expr::parse("(v3").is_err());
+        assert!(Expr::parse("vz == 1").is_err());
+        assert!(Expr::parse("v3 = 1").is_err());
+
+        let run = |tr: &mut Chip8Tester, dbg: &mut Debugger| {
+            for _ in 0..1000 {
+                tr.v.cycle();
+                if dbg.check(&tr.v) {
+                    return true;
+                }
+            }
+            false
+        };
+
+        // ADD V3, 1; JP 200
+        tr.reset();
+        tr.load_multiple(vec![0x7301, 0x1200], 0x200);
+        let mut dbg = Debugger::new();
+        dbg.command(&mut tr.v, "break 0x200 if v3 >= 3").unwrap();
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!(tr.v3(), 3);
+        dbg.command(&mut tr.v, "ignore 1 2").unwrap();
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!(tr.v3(), 6);
+        dbg.command(&mut tr.v, "condition 1 v3 == 0").unwrap();
+        assert!(run(&mut tr, &mut dbg));
+        assert_eq!(tr.v3(), 0);
+        assert!(dbg.command(&mut tr.v, "break 0x200 when v3").is_err());
+        assert!(dbg.command(&mut tr.v, "ignore 2 1").is_err());
+
+        let catch = |event: &str, program: Vec<u16>| {
+            let mut tr = Chip8Tester::new();
+            tr.load_multiple(program, 0x200);
+            let mut dbg = Debugger::new();
+            dbg.command(&mut tr.v, &format!("catch {}", event)).unwrap();
+            assert!(run(&mut tr, &mut dbg), "{}", event);
+            tr
+        };
+        // the second draw of the same sprite erases it
+        assert_eq!(catch("collision", vec![0xD005, 0xD005, 0x1204]).pc(), 0x204);
+        assert!(catch("key", vec![0x6001, 0xF10A]).v.awaiting_keypress);
+        assert_eq!(catch("sound", vec![0x6005, 0xF018, 0x1204]).pc(), 0x204);
+        let tr = catch("lowwrite", vec![0x60FF, 0xA100, 0xF033, 0x1206]);
+        assert_eq!((tr.pc(), tr.v.memory[0x100]), (0x206, 2));
+        // FX55 across 0x200 still writes 0x1ff first
+        assert_eq!(catch("lowwrite", vec![0xA1FF, 0xF155, 0x1204]).pc(), 0x204);
+        assert_eq!(catch("unknown", vec![0x6001, 0x0123]).pc(), 0x202);
+        let mut dbg = Debugger::new();
+        assert!(dbg.command(&mut Chip8Tester::new().v, "catch fire").is_err());
+    }
+
+    #[test]
+    fn reverse_test() {
+        use crate::debugger::{Action, Debugger};
+
+        let mut tr = Chip8Tester::new();
+        // RND V0, 0xFF; ADD V1, V0; SKP V3; ADD V2, 1; LD V4, DT; LD DT, V0; JP 200
+        tr.load_multiple(vec![0xC0FF, 0x8104, 0xE39E, 0x7201, 0xF407, 0xF015, 0x1200], 0x200);
+        let mut dbg = Debugger::new();
+        dbg.command(&mut tr.v, "info").unwrap();
+        let state = |c: &Chip8| (c.cycles, c.reg_pc, c.regs_v, c.delay_timer, c.keys);
+        let mut states = vec![state(&tr.v)];
+        // random numbers, key presses and timer ticks all have to come out the same
+        for frame in 0..400 {
+            tr.v.keys[0] = (100..200).contains(&frame);
+            tr.v.tick();
+            for _ in 0..10 {
+                tr.v.cycle();
+                assert!(!dbg.check(&tr.v));
+                states.push(state(&tr.v));
+            }
+        }
+
+        assert_eq!(dbg.command(&mut tr.v, "rs"), Ok(Action::Stepped));
+        assert_eq!(state(&tr.v), states[3999]);
+        dbg.command(&mut tr.v, "rs 2500").unwrap();
+        assert_eq!(state(&tr.v), states[1499]);
+        // going back forgets what came after, and running on makes a new future
+        // (no tick came before this cycle, so it's the same one again)
+        dbg.command(&mut tr.v, "s").unwrap();
+        assert_eq!(state(&tr.v), states[1500]);
+        dbg.command(&mut tr.v, "set v1 7").unwrap();
+        dbg.command(&mut tr.v, "s 5").unwrap();
+        dbg.command(&mut tr.v, "rs 5").unwrap();
+        assert_eq!((tr.v.cycles, tr.v1()), (1500, 7));
+
+        // the last place the breakpoint would have stopped before here
+        dbg.command(&mut tr.v, "break 0x206 if v2 == 50").unwrap();
+        let expected = states[..1500].iter().rposition(|s| s.1 == 0x206 && s.2[2] == 50).unwrap();
+        assert_eq!(dbg.command(&mut tr.v, "rc"), Ok(Action::Stepped));
+        assert_eq!(state(&tr.v), states[expected]);
+        // and before that, nothing
+        dbg.command(&mut tr.v, "rc").unwrap();
+        assert_eq!(state(&tr.v), states[0]);
+        assert!(dbg.command(&mut tr.v, "rs").is_err());
+
+        // instructions, not cycles: waiting for a key runs cycles but no instruction
+        let mut tr = Chip8Tester::new();
+        // LD V0, 5; LD V1, K; ADD V0, 1; JP 206
+        tr.load_multiple(vec![0x6005, 0xF10A, 0x7001, 0x1206], 0x200);
+        let mut dbg = Debugger::new();
+        dbg.command(&mut tr.v, "info").unwrap();
+        for cycle in 1..=6 {
+            // the key arrives on the third cycle of waiting
+            tr.v.keys[3] = cycle == 5;
+            tr.v.cycle();
+            dbg.check(&tr.v);
+        }
+        assert_eq!((tr.pc(), tr.v0(), tr.v1()), (0x206, 6, 3));
+        dbg.command(&mut tr.v, "rs").unwrap();
+        assert_eq!((tr.v.cycles, tr.pc(), tr.v0(), tr.v1()), (5, 0x204, 5, 3));
+        dbg.command(&mut tr.v, "rs").unwrap();
+        assert_eq!((tr.v.cycles, tr.pc(), tr.v.awaiting_keypress), (1, 0x202, false));
+        dbg.command(&mut tr.v, "rs").unwrap();
+        assert_eq!((tr.v.cycles, tr.pc()), (0, 0x200));
+        assert!(dbg.command(&mut tr.v, "rs").is_err());
+    }
+
+    #[test]
+    fn trace_test() {
+        use crate::trace::Tracer;
+
+        let mut tr = Chip8Tester::new();
+        // LD V0, 5; LD I, 0x300; LD V1, K; ADD V0, 1; JP 206
+        tr.load_multiple(vec![0x6005, 0xA300, 0xF10A, 0x7001, 0x1206], 0x200);
+        let dir = std::env::temp_dir();
+        let all = dir.join(format!("chip8-trace-{}-all.log", std::process::id()));
+        let some = dir.join(format!("chip8-trace-{}-some.log", std::process::id()));
+        let mut tracers = [
+            Tracer::new(all.to_str().unwrap(), None, None, &tr.v).unwrap(),
+            Tracer::new(some.to_str().unwrap(), Some(0x206..=0x206), Some(8..=u64::MAX), &tr.v).unwrap(),
+        ];
+        for cycle in 1..=9 {
+            // the key arrives while FX0A waits
+            tr.v.keys[2] = cycle >= 6;
+            tr.v.cycle();
+            for tracer in tracers.iter_mut() {
+                tracer.log(&tr.v);
+            }
+        }
+        drop(tracers);
+        let read = |path: &std::path::Path| {
+            let text = std::fs::read_to_string(path).unwrap();
+            std::fs::remove_file(path).unwrap();
+            text
+        };
+
+        let text = read(&all);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("         1 200 6005 v=05{} i=000 sp=00 dt=00 st=00  LD V0, 0x05", " 00".repeat(15))
+        );
+        // waiting for the key gets no lines
+        let cycles: Vec<&str> = lines.iter().map(|l| l.split_whitespace().next().unwrap()).collect();
+        assert_eq!(cycles, ["1", "2", "3", "7", "8", "9"]);
+        assert!(lines[3].starts_with("         7 206 7001 v=06 02 "));
+
+        let text = read(&some);
+        assert_eq!(text.lines().count(), 1);
+        assert!(text.starts_with("         9 206 7001 v=07 02 "));
+    }
+
+    #[test]
+    fn trace_diff_test() {
+        use crate::emu::Chip8Options;
+        use crate::trace::{diff, Tracer};
+
+        let trace = |options: Chip8Options, name: &str| {
+            let mut tr = Chip8Tester::with_options(options);
+            // LD V0, 5; LD V1, 6; LD I, 0x300; LD [I], V1; LD B, V0; JP 20A
+            tr.load_multiple(vec![0x6005, 0x6106, 0xA300, 0xF155, 0xF033, 0x120A], 0x200);
+            let path = std::env::temp_dir().join(format!("chip8-diff-{}-{}.log", std::process::id(), name));
+            let mut tracer = Tracer::new(path.to_str().unwrap(), None, None, &tr.v).unwrap();
+            for _ in 0..7 {
+                tr.v.cycle();
+                tracer.log(&tr.v);
+            }
+            drop(tracer);
+            let text = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            text
+        };
+        let a = trace(Chip8Options::default(), "a");
+        // FX55 leaving I past what it stored
+        let b = trace(Chip8Options { saving_increases_reg_i: true, ..Chip8Options::default() }, "b");
+        assert!(a.lines().nth(3).unwrap().ends_with("LD [I], V1 ; 300: 05 06"));
+
+        assert_eq!(diff(&a, &a, 2), Ok(None));
+        let report = diff(&a, &b, 1).unwrap().unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[..3], ["First difference at instruction 4:", "  I: 300 / 302", ""]);
+        assert!(lines[3].starts_with("           3 204 a300 "));
+        assert!(lines[4].starts_with("<          4 206 f155 ") && lines[4].contains("i=300"));
+        assert!(lines[5].ends_with("LD B, V0 ; 300: 00 00 05"));
+        assert!(lines[6].starts_with(">          4 206 f155 ") && lines[6].contains("i=302"));
+        assert!(lines[7].ends_with("LD B, V0 ; 302: 00 00 05"));
+
+        // the BCD lands somewhere else, too
+        let moved = b.replace("i=302", "i=300");
+        let report = diff(&a, &moved, 0).unwrap().unwrap();
+        assert!(report.starts_with("First difference at instruction 5:\n  wrote: 300: 00 00 05 / 302: 00 00 05\n"));
+
+        let short: String = a.lines().take(3).map(|l| format!("{}\n", l)).collect();
+        assert!(diff(&a, &short, 0).unwrap().unwrap().starts_with("The second trace ends after 3 instructions"));
+        assert!(diff(&a, "not a trace", 0).is_err());
+    }
+
+    #[test]
+    fn profile_test() {
+        use crate::profile::Profiler;
+
+        let mut tr = Chip8Tester::new();
+        // LD V0, 0; loop: ADD V0, 1; SE V0, 10; JP loop; JP 208 (forever)
+        tr.load_multiple(vec![0x6000, 0x7001, 0x300A, 0x1202, 0x1208], 0x200);
+        // two bytes of ROM that never run
+        let mut profiler = Profiler::new(&tr.v, 12);
+        for _ in 0..40 {
+            tr.v.cycle();
+            profiler.log(&tr.v);
+        }
+
+        let report = profiler.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "Instructions run: 40");
+        assert_eq!(lines[1], "ROM run: 10 of 12 bytes (83.3%)");
+        assert_eq!(lines[3], "By instruction:");
+        assert_eq!(lines[4], "          19  47.5%  1NNN (JP)");
+        assert_eq!(lines[7], "           1   2.5%  6XNN (LD)");
+        assert_eq!(lines[9], "Hot spots:");
+        // ties in address order
+        assert_eq!(lines[10], "          10  25.0%  202: 7001  ADD V0, 0x01");
+        assert_eq!(lines[12], "          10  25.0%  208: 1208  JP 0x208");
+        assert_eq!(lines[14], "           1   2.5%  200: 6000  LD V0, 0x00");
+
+        let heatmap = profiler.heatmap();
+        assert_eq!(heatmap.len(), 0x1000);
+        assert_eq!((heatmap[0x202], heatmap[0x209]), (0xFFFFFF, 0xFFFFFF));
+        assert!(heatmap[0x200] != 0xFFFFFF && heatmap[0x200] != 0);
+        assert_eq!((heatmap[0x20A], heatmap[0x20C], heatmap[0x100]), (0x202050, 0, 0));
+
+        let path = std::env::temp_dir().join(format!("chip8-heatmap-{}.bmp", std::process::id()));
+        profiler.write_heatmap(path.to_str().unwrap()).unwrap();
+        let bmp = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&bmp[..2], b"BM");
+        assert_eq!(bmp.len(), 54 + 512 * 512 * 3);
+        assert_eq!(u32::from_le_bytes(bmp[18..22].try_into().unwrap()), 512);
+        // the bottom row comes first: address 0xFC0 onwards
+        assert_eq!(&bmp[54..57], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn access_test() {
+        use crate::accessmap;
+        use crate::disasm::disassemble_mapped;
+
+        let mut tr = Chip8Tester::new();
+        // LD I, 0x20E; DRW V0, V1, 1; LD V0, 0x12; LD V1, 0x0C; LD I, 0x20C; LD [I], V1;
+        // then what that wrote: JP 20C; and a sprite
+        tr.load_multiple(vec![0xA20E, 0xD011, 0x6012, 0x610C, 0xA20C, 0xF155, 0x0000, 0xF000], 0x200);
+        let mut plain = tr.v.clone();
+        tr.v.record_access();
+        for _ in 0..8 {
+            tr.v.cycle();
+            plain.cycle();
+        }
+        assert!(plain.access.is_none());
+
+        let access = tr.v.access.as_ref().unwrap();
+        let text = accessmap::export(access);
+        assert_eq!(text, "200-20b x---\n20c-20d x-wm\n20e-20e -r--\n");
+        assert_eq!(accessmap::parse(&text).as_ref(), Ok(access));
+        assert!(accessmap::parse("200-1ff x---").is_err());
+        assert!(accessmap::parse("200-2ff xyz").is_err());
+
+        // FX33's digits count as read as well as written
+        let mut bcd = Chip8Tester::new();
+        bcd.load_multiple(vec![0xA300, 0xF033], 0x200);
+        bcd.v.record_access();
+        bcd.v.cycle();
+        bcd.v.cycle();
+        assert_eq!(accessmap::export(bcd.v.access.as_ref().unwrap()), "200-203 x---\n300-302 -rw-\n");
+
+        let lines = disassemble_mapped(&tr.v.memory, access, 0x208, 0x210);
+        assert_eq!(
+            lines,
+            [
+                "208: a20c  LD I, 0x20c",
+                "20a: f155  LD [I], V1",
+                "20c: 120c  JP 0x20c  ; modified",
+                "20e: f0    DB 0xf0  ; read",
+                "20f: 00    DB 0x00",
+            ]
+        );
+    }
+
+    #[test]
+    fn overlay_test() {
+        use crate::font::{glyph, FONT_DATA};
+        use crate::overlay;
+
+        assert_eq!(glyph('0'), FONT_DATA[0..5]);
+        assert_eq!(glyph('f'), FONT_DATA[75..80]);
+        assert_eq!(glyph('g'), glyph('G'));
+        assert_ne!(glyph('G'), glyph('~'));
+        assert_eq!(glyph(' '), [0; 5]);
+
+        let mut tr = Chip8Tester::new();
+        tr.load_multiple(vec![0x6005], 0x200);
+        let pixel = |rgba: &[u8], x: usize, y: usize| {
+            let start = (y * overlay::WIDTH + x) * 4;
+            rgba[start..start + 4].to_vec()
+        };
+        let rgba = overlay::render(&tr.v);
+        assert_eq!(rgba.len(), overlay::WIDTH * overlay::HEIGHT * 4);
+        // the top left of the P of PC, and the see-through background
+        assert_eq!(pixel(&rgba, 2, 2), [0x80, 0xC0, 0xFF, 0xFF]);
+        assert_eq!(pixel(&rgba, 0, 0), [0, 0, 0, 0xC0]);
+        // key 1 lights up when it's down
+        assert_eq!(pixel(&rgba, 201, 29), [0, 0, 0, 0xC0]);
+        tr.v.keys[1] = true;
+        let rgba = overlay::render(&tr.v);
+        assert_eq!(pixel(&rgba, 201, 29), [0xFF, 0xCC, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn viewer_test() {
+        use crate::viewer::{self, MemoryViewer, SpriteViewer};
+
+        let mut tr = Chip8Tester::new();
+        // LD V0, 0x10
+        tr.load_multiple(vec![0x6010], 0x200);
+        tr.v.reg_i = 0x200;
+        let pixel = |rgba: &[u8], width: usize, x: usize, y: usize| {
+            let start = (y * width + x) * 4;
+            rgba[start..start + 4].to_vec()
+        };
+
+        let mut memory = MemoryViewer::new(&tr.v);
+        let rgba = memory.render(&tr.v);
+        assert_eq!(rgba.len(), viewer::MEMORY_WIDTH * viewer::MEMORY_HEIGHT * 4);
+        // the bytes at I are on blue
+        assert_eq!(pixel(&rgba, viewer::MEMORY_WIDTH, 26, 1), [0x20, 0x40, 0x80, 0xFF]);
+        // 201 was 10 and is written with 00, lighting up the 0, then fades
+        tr.v.memory[0x201] = 0x00;
+        memory.update(&tr.v);
+        let rgba = memory.render(&tr.v);
+        assert_eq!(pixel(&rgba, viewer::MEMORY_WIDTH, 43, 2), [0xFF, 0xCC, 0x00, 0xFF]);
+        for _ in 0..60 {
+            memory.update(&tr.v);
+        }
+        let rgba = memory.render(&tr.v);
+        assert_eq!(pixel(&rgba, viewer::MEMORY_WIDTH, 43, 2), [0xFF, 0xFF, 0xFF, 0xFF]);
+        // scrolled off the top, then back to the end
+        memory.scroll(-1000);
+        let rgba = memory.render(&tr.v);
+        assert_eq!(pixel(&rgba, viewer::MEMORY_WIDTH, 26, 1), [0, 0, 0, 0xFF]);
+        memory.show(0xFFF);
+        memory.show(0x200);
+        assert_eq!(pixel(&memory.render(&tr.v), viewer::MEMORY_WIDTH, 26, 1), [0x20, 0x40, 0x80, 0xFF]);
+
+        let mut sprites = SpriteViewer::new();
+        assert_eq!(sprites.title(), "chip8 sprites - 200-47f, 8 rows each");
+        // 60 is .xx.....
+        let rgba = sprites.render(&tr.v);
+        assert_eq!(rgba.len(), viewer::SPRITES_WIDTH * viewer::SPRITES_HEIGHT * 4);
+        assert_eq!(pixel(&rgba, viewer::SPRITES_WIDTH, 22, 2), [0x30, 0x30, 0x30, 0xFF]);
+        assert_eq!(pixel(&rgba, viewer::SPRITES_WIDTH, 23, 2), [0xFF, 0xFF, 0xFF, 0xFF]);
+        // the font's 0 is 5 rows
+        sprites.move_by(-0x1000);
+        sprites.resize(-3);
+        assert_eq!(sprites.title(), "chip8 sprites - 000-22f, 5 rows each");
+        sprites.resize(100);
+        sprites.show(0xFF0);
+        assert_eq!(sprites.title(), "chip8 sprites - ff0-fff, 16 rows each");
+    }
+
+    #[test]
+    fn gdb_test() {
+        use crate::gdb::GdbServer;
+        use std::io::{Read, Write};
+
+        let mut tr = Chip8Tester::new();
+        // LD V0, 5; LD I, 0x300; LD [I], V0; ADD V0, 1; JP 206
+        tr.load_multiple(vec![0x6005, 0xA300, 0xF055, 0x7001, 0x1206], 0x200);
+        let mut gdb = GdbServer::new(0).unwrap();
+        let mut client = std::net::TcpStream::connect(("127.0.0.1", gdb.port())).unwrap();
+        client.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        while !gdb.connected() {
+            gdb.poll(&mut tr.v);
+        }
+        let framed = |data: &str| {
+            let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            format!("${}#{:02x}", data, checksum)
+        };
+        let reply = |client: &mut std::net::TcpStream| {
+            let mut out = Vec::new();
+            let mut byte = [0];
+            while out.len() < 3 || out[out.len() - 3] != b'#' {
+                client.read_exact(&mut byte).unwrap();
+                out.push(byte[0]);
+            }
+            String::from_utf8(out).unwrap()
+        };
+        // sends a packet and waits for it to be acknowledged
+        let send = |tr: &mut Chip8Tester, gdb: &mut GdbServer, client: &mut std::net::TcpStream, packet: &str| {
+            client.write_all(framed(packet).as_bytes()).unwrap();
+            client.set_nonblocking(true).unwrap();
+            let mut ack = [0];
+            loop {
+                gdb.poll(&mut tr.v);
+                if client.read(&mut ack).is_ok() {
+                    break;
+                }
+            }
+            client.set_nonblocking(false).unwrap();
+            assert_eq!(ack[0], b'+');
+        };
+        let request = |tr: &mut Chip8Tester, gdb: &mut GdbServer, client: &mut std::net::TcpStream, packet: &str| {
+            send(tr, gdb, client, packet);
+            reply(client)
+        };
+
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "?"), framed("S05"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "s"), framed("S05"));
+        // V0-VF, then I and PC little-endian, SP, DT and ST
+        let regs = request(&mut tr, &mut gdb, &mut client, "g");
+        assert_eq!(&regs[1..regs.len() - 3], format!("05{}00000202000000", "00".repeat(15)));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "P10=2003"), framed("OK"));
+        assert_eq!(tr.v.reg_i, 0x320);
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "p11"), framed("0202"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "P11=0020"), framed("E02"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "m200,2"), framed("6005"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "M300,2:abcd"), framed("OK"));
+        assert_eq!(tr.v.memory[0x301], 0xCD);
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "m1000,1"), framed("E01"));
+        // bytes that aren't ASCII are an error, not a crash
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "M300,2:a\u{e9}0"), framed("E01"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "\u{20ac}"), framed("E01"));
+        // unsupported packets get an empty reply
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "Z3,300,1"), framed(""));
+
+        // a write watchpoint, then a breakpoint
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "P10=0003"), framed("OK"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "Z2,300,1"), framed("OK"));
+        send(&mut tr, &mut gdb, &mut client, "c");
+        assert!(gdb.running());
+        for _ in 0..100 {
+            tr.v.cycle();
+            if gdb.check(&tr.v) {
+                break;
+            }
+        }
+        assert_eq!(reply(&mut client), framed("T05watch:300;"));
+        assert_eq!((tr.pc(), tr.v.memory[0x300]), (0x206, 5));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "z2,300,1"), framed("OK"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "Z0,208,2"), framed("OK"));
+        send(&mut tr, &mut gdb, &mut client, "c");
+        while !gdb.check(&tr.v) {
+            tr.v.cycle();
+        }
+        assert_eq!(reply(&mut client), framed("S05"));
+        assert_eq!(tr.pc(), 0x208);
+
+        // a breakpoint on a jump to itself is hit every time round
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "z0,208,2"), framed("OK"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "M20a,2:120a"), framed("OK"));
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "Z0,20a,2"), framed("OK"));
+        for _ in 0..2 {
+            send(&mut tr, &mut gdb, &mut client, "c20a");
+            let cycles = tr.v.cycles;
+            for _ in 0..100 {
+                tr.v.cycle();
+                if gdb.check(&tr.v) {
+                    break;
+                }
+            }
+            assert_eq!(reply(&mut client), framed("S05"));
+            assert_eq!((tr.pc(), tr.v.cycles), (0x20a, cycles + 1));
+        }
+        assert_eq!(request(&mut tr, &mut gdb, &mut client, "z0,20a,2"), framed("OK"));
+
+        // Ctrl-C stops a running machine
+        send(&mut tr, &mut gdb, &mut client, "c");
+        client.write_all(&[0x03]).unwrap();
+        while gdb.running() {
+            gdb.poll(&mut tr.v);
+        }
+        assert_eq!(reply(&mut client), framed("S02"));
+        assert!(!gdb.running());
+        send(&mut tr, &mut gdb, &mut client, "k");
+        assert!(!gdb.connected());
+    }
+
     struct Chip8Tester {
         pub v: Chip8
     }
@@ -167,10 +1212,15 @@ mod tests {
             let chip8rom = [0; 0xe00];
             Chip8Tester {
                 v: Chip8::new(chip8rom, crate::emu::Chip8Options { 
-                    saving_increases_reg_i: false, shift_vy_not_vx: false 
+                    saving_increases_reg_i: false, shift_vy_not_vx: false, display_wait: false
                 })
             }
         }
+        fn with_options(options: crate::emu::Chip8Options) -> Chip8Tester {
+            Chip8Tester {
+                v: Chip8::new([0; 0xe00], options)
+            }
+        }
         fn reset(&mut self) {
             self.v.memory = [0; 0x1000];
             self.v.reg_i = 0;
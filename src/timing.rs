@@ -0,0 +1,140 @@
+use crate::emu::Chip8;
+use std::time::Duration;
+
+pub const TIMER_HZ: u64 = 60;
+
+// The COSMAC VIP's 1802 runs at 1.7609 MHz with 8 clocks per machine cycle,
+// and the 1861 display chip halts it for 8 cycles of DMA on each of the 128
+// scanlines it shows per frame.
+pub const VIP_CLOCK_HZ: u64 = 1_760_900;
+const VIP_CYCLES_PER_FRAME: i64 = (VIP_CLOCK_HZ / 8 / TIMER_HZ) as i64;
+const VIP_DMA_CYCLES_PER_FRAME: i64 = 128 * 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timing {
+    // a fixed number of instructions per second
+    Ips,
+    // instructions cost what they did on the COSMAC VIP (see `emu::vip_cycles`),
+    // out of the machine cycles the VIP had available per frame
+    Vip,
+}
+
+// don't try to catch up on more than this after a stall (window drag, breakpoint...)
+const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+
+// Paces emulation against the wall clock, independently of how often the
+// frontend renders. Time is handed out in 60 Hz frames: each frame ticks the
+// timers once and runs ips/60 instructions (or a VIP frame's worth of machine
+// cycles), with whatever is left over carried to the next frame.
+pub struct Pacer {
+    ips: f64,
+    timing: Timing,
+    // elapsed nanoseconds * TIMER_HZ, so that a frame is exactly 1e9
+    time_owed: u128,
+    // instructions * TIMER_HZ
+    cycles_owed: f64,
+    // VIP machine cycles; goes negative when an instruction runs past the frame
+    vip_cycles_owed: i64,
+    frames: u64,
+}
+
+impl Pacer {
+    pub fn new(ips: f64, timing: Timing) -> Pacer {
+        Pacer {
+            ips,
+            timing,
+            time_owed: 0,
+            cycles_owed: 0.0,
+            vip_cycles_owed: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn ips(&self) -> f64 {
+        self.ips
+    }
+
+    pub fn set_ips(&mut self, ips: f64) {
+        self.ips = ips;
+    }
+
+    // frames run so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // runs however many frames have become due; returns the instructions executed
+    pub fn advance(&mut self, chip8: &mut Chip8, elapsed: Duration) -> u64 {
+        let mut cycles = 0;
+        self.advance_until(chip8, elapsed, |_| {
+            cycles += 1;
+            false
+        });
+        cycles
+    }
+
+    // Like `advance`, but `stop` is called after every instruction and ends
+    // the run early when it returns true (for breakpoints). The rest of that
+    // frame's instructions are dropped. Returns whether it stopped.
+    pub fn advance_until(
+        &mut self,
+        chip8: &mut Chip8,
+        elapsed: Duration,
+        mut stop: impl FnMut(&Chip8) -> bool,
+    ) -> bool {
+        self.time_owed += elapsed.min(MAX_CATCH_UP).as_nanos() * TIMER_HZ as u128;
+        while self.time_owed >= 1_000_000_000 {
+            self.time_owed -= 1_000_000_000;
+            if self.run_frame_until(chip8, &mut stop) {
+                // don't make up for the time spent stopped
+                self.time_owed = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    // one 60 Hz frame, regardless of the clock
+    pub fn run_frame(&mut self, chip8: &mut Chip8) -> u64 {
+        let mut cycles = 0;
+        self.run_frame_until(chip8, |_| {
+            cycles += 1;
+            false
+        });
+        cycles
+    }
+
+    pub fn run_frame_until(&mut self, chip8: &mut Chip8, mut stop: impl FnMut(&Chip8) -> bool) -> bool {
+        self.frames += 1;
+        chip8.tick();
+        if self.timing == Timing::Vip {
+            self.vip_cycles_owed += VIP_CYCLES_PER_FRAME - VIP_DMA_CYCLES_PER_FRAME;
+            while self.vip_cycles_owed > 0 {
+                self.vip_cycles_owed -= chip8.cycle() as i64;
+                if chip8.awaiting_vblank {
+                    // the rest of the frame is spent waiting for the interrupt
+                    self.vip_cycles_owed = 0;
+                }
+                if stop(chip8) {
+                    self.vip_cycles_owed = self.vip_cycles_owed.min(0);
+                    return true;
+                }
+            }
+            return false;
+        }
+        self.cycles_owed += self.ips;
+        let cycles = (self.cycles_owed / TIMER_HZ as f64) as u64;
+        self.cycles_owed -= (cycles * TIMER_HZ) as f64;
+        for _ in 0..cycles {
+            chip8.cycle();
+            if stop(chip8) {
+                return true;
+            }
+        }
+        false
+    }
+}
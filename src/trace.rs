@@ -0,0 +1,218 @@
+use crate::disasm::disassemble;
+use crate::emu::Chip8;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+
+// Writes a line per instruction run, for lining up against other emulators'
+// logs (or this one's, after a change) to find where they part ways:
+//
+//        123 204 7001 v=06 00 00 .. 00 i=300 sp=00 dt=00 st=00  ADD V0, 0x01
+//
+// The cycle (counting calls to `cycle`, so waits for a key or the display
+// take up numbers but get no line), the instruction's address and opcode,
+// then the registers after it ran. Everything before the mnemonic is fixed
+// width, so columns can be cut out with `cut -c`. Instructions that write
+// memory (FX33, FX55) end with what they wrote: `  LD B, V0 ; 300: 01 02 03`.
+pub struct Tracer {
+    out: Option<BufWriter<File>>,
+    path: String,
+    // only instructions at these addresses, and run in these cycles
+    pcs: Option<RangeInclusive<usize>>,
+    cycles: Option<RangeInclusive<u64>>,
+    follower: Follower,
+}
+
+impl Tracer {
+    pub fn new(
+        path: &str,
+        pcs: Option<RangeInclusive<usize>>,
+        cycles: Option<RangeInclusive<u64>>,
+        chip8: &Chip8,
+    ) -> Result<Tracer, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Tracer {
+            out: Some(BufWriter::new(file)),
+            path: path.to_string(),
+            pcs,
+            cycles,
+            follower: Follower::new(chip8),
+        })
+    }
+
+    // Called after every cycle. Cycles run without being traced (stepping in
+    // the debugger) are skipped over.
+    pub fn log(&mut self, chip8: &Chip8) {
+        let ran = match self.follower.update(chip8) {
+            Some(ran) => ran,
+            None => return,
+        };
+        let traced = self.pcs.as_ref().is_none_or(|r| r.contains(&ran.pc))
+            && self.cycles.as_ref().is_none_or(|r| r.contains(&chip8.cycles));
+        if traced {
+            let line = line(chip8.cycles, &ran, chip8);
+            if let Some(out) = self.out.as_mut() {
+                if let Err(e) = writeln!(out, "{}", line) {
+                    eprintln!("Error writing the trace to {}: {}", self.path, e);
+                    self.out = None;
+                }
+            }
+        }
+    }
+}
+
+// an instruction that ran
+pub struct Ran {
+    pub pc: usize,
+    pub opcode: u16,
+    // I before it ran
+    pub i: usize,
+}
+
+// Follows the machine from one cycle to the next, to tell which instruction
+// (if any) each one ran.
+pub struct Follower {
+    cycles: u64,
+    ticks: u64,
+    next: Ran,
+    waiting: bool,
+    vblank: bool,
+}
+
+impl Follower {
+    pub fn new(chip8: &Chip8) -> Follower {
+        Follower {
+            cycles: chip8.cycles,
+            ticks: chip8.ticks,
+            next: Ran {
+                pc: chip8.reg_pc,
+                opcode: chip8.opcode(),
+                i: chip8.reg_i,
+            },
+            waiting: chip8.awaiting_keypress,
+            vblank: chip8.awaiting_vblank,
+        }
+    }
+
+    // Called after every cycle: what it ran, or None for a wait (or when
+    // cycles ran that weren't followed, so what they were isn't known).
+    pub fn update(&mut self, chip8: &Chip8) -> Option<Ran> {
+        // a tick between cycles ends the wait for the display
+        let waited = self.waiting || (self.vblank && chip8.ticks == self.ticks);
+        let followed = chip8.cycles == self.cycles + 1;
+        let last = std::mem::replace(self, Follower::new(chip8));
+        (followed && !waited).then_some(last.next)
+    }
+}
+
+// the instruction having run, leaving the machine as `chip8`
+fn line(cycle: u64, ran: &Ran, chip8: &Chip8) -> String {
+    let (pc, opcode, i) = (ran.pc, ran.opcode, ran.i);
+    let v: Vec<String> = chip8.regs_v.iter().map(|v| format!("{:02x}", v)).collect();
+    let mut line = format!(
+        "{:10} {:03x} {:04x} v={} i={:03x} sp={:02x} dt={:02x} st={:02x}  {}",
+        cycle,
+        pc,
+        opcode,
+        v.join(" "),
+        chip8.reg_i,
+        chip8.stack_pointer,
+        chip8.delay_timer,
+        chip8.sound_timer,
+        disassemble(opcode)
+    );
+    let written = match opcode & 0xF0FF {
+        0xF033 => 3,
+        0xF055 => ((opcode >> 8) & 0xF) as usize + 1,
+        _ => 0,
+    };
+    if written > 0 {
+        let bytes: Vec<String> = (i..i + written).map(|addr| format!("{:02x}", chip8.memory[addr & 0xFFF])).collect();
+        line.push_str(&format!(" ; {:03x}: {}", i & 0xFFF, bytes.join(" ")));
+    }
+    line
+}
+
+// the columns of a trace line after the cycle
+static FIELDS: [&str; 22] = [
+    "PC", "opcode", "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF",
+    "I", "SP", "DT", "ST",
+];
+
+// a trace line's state, without the cycle (which depends on timing)
+struct Entry<'a> {
+    line: &'a str,
+    fields: Vec<&'a str>,
+    writes: Option<&'a str>,
+}
+
+fn parse_line(line: &str) -> Option<Entry<'_>> {
+    let mut words = line.split_whitespace().skip(1);
+    let mut fields = Vec::with_capacity(FIELDS.len());
+    for name in FIELDS.iter() {
+        let word = words.next()?;
+        let prefix = match *name {
+            "V0" => "v=",
+            "I" => "i=",
+            "SP" => "sp=",
+            "DT" => "dt=",
+            "ST" => "st=",
+            _ => "",
+        };
+        fields.push(word.strip_prefix(prefix)?);
+    }
+    Some(Entry {
+        line,
+        fields,
+        writes: line.split_once(" ; ").map(|(_, writes)| writes),
+    })
+}
+
+fn parse_trace<'a>(text: &'a str, name: &str) -> Result<Vec<Entry<'a>>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| parse_line(line).ok_or_else(|| format!("{} line {}: not a trace line: {}", name, n + 1, line)))
+        .collect()
+}
+
+// Compares two traces instruction by instruction, ignoring the cycle
+// column. Returns a report on the first difference, with `context` lines
+// either side, or None if they match.
+pub fn diff(a: &str, b: &str, context: usize) -> Result<Option<String>, String> {
+    let a = parse_trace(a, "first trace")?;
+    let b = parse_trace(b, "second trace")?;
+    let same = |x: &Entry, y: &Entry| x.fields == y.fields && x.writes == y.writes;
+    let n = match a.iter().zip(&b).position(|(x, y)| !same(x, y)) {
+        Some(n) => n,
+        None if a.len() == b.len() => return Ok(None),
+        None => a.len().min(b.len()),
+    };
+    let mut report = String::new();
+    match (a.get(n), b.get(n)) {
+        (Some(x), Some(y)) => {
+            report.push_str(&format!("First difference at instruction {}:\n", n + 1));
+            for ((name, p), q) in FIELDS.iter().zip(&x.fields).zip(&y.fields) {
+                if p != q {
+                    report.push_str(&format!("  {}: {} / {}\n", name, p, q));
+                }
+            }
+            if x.writes != y.writes {
+                let writes = |w: Option<&str>| w.unwrap_or("nothing").to_string();
+                report.push_str(&format!("  wrote: {} / {}\n", writes(x.writes), writes(y.writes)));
+            }
+        }
+        (Some(_), None) => report.push_str(&format!("The second trace ends after {} instructions\n", n)),
+        _ => report.push_str(&format!("The first trace ends after {} instructions\n", n)),
+    }
+    report.push('\n');
+    for entry in &a[n.saturating_sub(context)..n] {
+        report.push_str(&format!("  {}\n", entry.line));
+    }
+    for (marker, trace) in [('<', &a), ('>', &b)] {
+        for entry in trace.iter().skip(n).take(context + 1) {
+            report.push_str(&format!("{} {}\n", marker, entry.line));
+        }
+    }
+    Ok(Some(report))
+}
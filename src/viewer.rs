@@ -0,0 +1,178 @@
+use crate::emu::Chip8;
+use crate::overlay::{Panel, CELL_HEIGHT, CELL_WIDTH, LABEL, MARGIN, VALUE};
+use crate::palette::blend;
+
+// What goes in the memory and sprite viewer windows. The windows themselves
+// are main's; these keep where each is looking and draw it, as RGBA.
+
+// ARGB
+const BACKGROUND: u32 = 0xFF000000;
+const WRITTEN: u32 = 0xFFFFCC00;
+const AT_I: u32 = 0xFF204080;
+const SPRITE_ON: u32 = 0xFFFFFFFF;
+const SPRITE_OFF: u32 = 0xFF303030;
+
+// The memory viewer: a hex dump, 16 bytes a line, scrolled through memory.
+// Bytes written lately are yellow, fading back to white, and the 16 bytes
+// from I (all a sprite or FX55 can use) are on blue.
+//
+//   200: 00 e0 a2 2a 60 0c 61 08 d0 1f 70 09 a2 39 d0 1f
+pub const MEMORY_ROWS: usize = 32;
+const BYTES_PER_ROW: usize = 16;
+// the address, then 3 characters a byte
+pub const MEMORY_WIDTH: usize = 2 * MARGIN + (5 + 3 * BYTES_PER_ROW - 1) * CELL_WIDTH;
+pub const MEMORY_HEIGHT: usize = 2 * MARGIN + MEMORY_ROWS * CELL_HEIGHT;
+// how long a write stays lit, in frames
+const FADE_FRAMES: u8 = 60;
+
+pub struct MemoryViewer {
+    // the address of the top line
+    top: usize,
+    // memory as of the last update, to spot writes
+    last: Vec<u8>,
+    // for each byte, the frames left before its last write fades
+    ages: Vec<u8>,
+}
+
+impl MemoryViewer {
+    pub fn new(chip8: &Chip8) -> MemoryViewer {
+        MemoryViewer {
+            top: 0x200,
+            last: chip8.memory.to_vec(),
+            ages: vec![0; chip8.memory.len()],
+        }
+    }
+
+    // called once a frame
+    pub fn update(&mut self, chip8: &Chip8) {
+        for (addr, &byte) in chip8.memory.iter().enumerate() {
+            self.ages[addr] = if byte != self.last[addr] {
+                FADE_FRAMES
+            } else {
+                self.ages[addr].saturating_sub(1)
+            };
+        }
+        self.last.copy_from_slice(&chip8.memory);
+    }
+
+    pub fn scroll(&mut self, rows: isize) {
+        let bottom = self.last.len() - MEMORY_ROWS * BYTES_PER_ROW;
+        let top = self.top as isize + rows * BYTES_PER_ROW as isize;
+        self.top = top.clamp(0, bottom as isize) as usize;
+    }
+
+    // scrolls so the line with `addr` is at the top, or as near as it goes
+    pub fn show(&mut self, addr: usize) {
+        self.top = 0;
+        self.scroll(((addr & 0xFFF) / BYTES_PER_ROW) as isize);
+    }
+
+    pub fn render(&self, chip8: &Chip8) -> Vec<u8> {
+        let mut panel = Panel::new(MEMORY_WIDTH, MEMORY_HEIGHT, BACKGROUND);
+        let at_i = chip8.reg_i..chip8.reg_i + 16;
+        for row in 0..MEMORY_ROWS {
+            let start = self.top + row * BYTES_PER_ROW;
+            panel.text(0, row, &format!("{:03x}:", start), LABEL);
+            for n in 0..BYTES_PER_ROW {
+                let addr = start + n;
+                let col = 5 + n * 3;
+                if at_i.contains(&addr) {
+                    let x = MARGIN + col * CELL_WIDTH - 1;
+                    let y = MARGIN + row * CELL_HEIGHT - 1;
+                    panel.fill(x, y, 2 * CELL_WIDTH + 1, CELL_HEIGHT, AT_I);
+                }
+                let age = self.ages[addr] as u32;
+                let color = if age > 0 {
+                    0xFF000000 | blend(WRITTEN, VALUE, age * 256 / FADE_FRAMES as u32)
+                } else {
+                    VALUE
+                };
+                panel.text(col, row, &format!("{:02x}", chip8.memory[addr]), color);
+            }
+        }
+        panel.rgba()
+    }
+}
+
+// The sprite viewer: memory from any address on, cut into 8-pixel-wide
+// sprites of some number of rows, 8 to a line with the address of the first.
+// Unlit pixels are dark grey so each sprite's outline shows.
+const SPRITES_PER_ROW: usize = 8;
+const SPRITE_GAP: usize = 4;
+// an address, then the sprites
+const SPRITES_LEFT: usize = MARGIN + 4 * CELL_WIDTH;
+pub const SPRITES_WIDTH: usize = SPRITES_LEFT + SPRITES_PER_ROW * (8 + SPRITE_GAP) - SPRITE_GAP + MARGIN;
+pub const SPRITES_HEIGHT: usize = 120;
+
+pub struct SpriteViewer {
+    start: usize,
+    // each sprite's height
+    rows: usize,
+}
+
+impl SpriteViewer {
+    pub fn new() -> SpriteViewer {
+        SpriteViewer { start: 0x200, rows: 8 }
+    }
+
+    // the height of a line of sprites, leaving room for the address
+    fn line_height(&self) -> usize {
+        self.rows.max(CELL_HEIGHT - 2) + 3
+    }
+
+    // how many lines of sprites fit
+    fn lines(&self) -> usize {
+        (SPRITES_HEIGHT - MARGIN) / self.line_height()
+    }
+
+    // bytes in a line of sprites, and in all that fit
+    pub fn line_len(&self) -> usize {
+        SPRITES_PER_ROW * self.rows
+    }
+
+    pub fn page_len(&self) -> usize {
+        self.line_len() * self.lines()
+    }
+
+    pub fn show(&mut self, addr: usize) {
+        self.start = addr & 0xFFF;
+    }
+
+    // moves the start by some bytes, to line up with the sprites
+    pub fn move_by(&mut self, bytes: isize) {
+        self.start = (self.start as isize + bytes).clamp(0, 0xFFF) as usize;
+    }
+
+    // sprites from 1 to 16 rows high
+    pub fn resize(&mut self, rows: isize) {
+        self.rows = (self.rows as isize + rows).clamp(1, 16) as usize;
+    }
+
+    pub fn title(&self) -> String {
+        let end = (self.start + self.page_len()).min(0x1000) - 1;
+        format!("chip8 sprites - {:03x}-{:03x}, {} rows each", self.start, end, self.rows)
+    }
+
+    pub fn render(&self, chip8: &Chip8) -> Vec<u8> {
+        let mut panel = Panel::new(SPRITES_WIDTH, SPRITES_HEIGHT, BACKGROUND);
+        for line in 0..self.lines() {
+            let y = MARGIN + line * self.line_height();
+            let start = self.start + line * self.line_len();
+            if start >= chip8.memory.len() {
+                break;
+            }
+            panel.text_at(MARGIN, y, &format!("{:03x}", start), LABEL);
+            for n in 0..SPRITES_PER_ROW {
+                let x = SPRITES_LEFT + n * (8 + SPRITE_GAP);
+                let sprite = start + n * self.rows;
+                for (row, &byte) in chip8.memory.iter().skip(sprite).take(self.rows).enumerate() {
+                    for bit in 0..8 {
+                        let color = if byte & (0x80 >> bit) != 0 { SPRITE_ON } else { SPRITE_OFF };
+                        panel.fill(x + bit, y + row, 1, 1, color);
+                    }
+                }
+            }
+        }
+        panel.rgba()
+    }
+}